@@ -1,91 +1,129 @@
-use std::fs::DirEntry;
-use std::path::Path;
+#[path = "build/documents.rs"]
+mod documents;
+#[path = "build/shaders.rs"]
+mod shaders;
+use documents::*;
+use shaders::*;
+use std::path::{Path, PathBuf};
 
 fn main() -> std::io::Result<()> {
     let hash = download_selfie();
-    out_texts("./texts", "/texts.rs", "&'static str", &markdown2html)?;
-    let closure = move |shader| parse_shader(shader, &hash);
-    out_texts("./shaders", "/shaders.rs", "ShaderSource", &closure)
+    let validator = ShaderValidator::new(&std::fs::read_to_string(FRAGMENT_SHADER_PREFIX)?);
+    let shader_names = out_shaders("./shaders", "/shaders.rs", &hash, validator)?;
+    out_documents("./texts", "/texts.rs", &shader_names)
 }
 
-fn out_texts(
+fn out_documents(
     input_dir_path: &str,
     out_dir_path: &str,
-    value_type: &str,
-    closure: &impl Fn(String) -> String,
+    shader_names: &[String],
 ) -> std::io::Result<()> {
-    let mut out_code = format!("use std::collections::HashMap;pub(crate) fn get_texts()->HashMap<&'static str,{value_type}>{{vec![");
-    set_text_dir(&mut out_code, input_dir_path, closure)?;
-    out_code += "].into_iter().collect()}";
-    std::fs::write(std::env::var("OUT_DIR").unwrap() + out_dir_path, &out_code)
-}
-
-fn set_text_dir(
-    out_code: &mut String,
-    path: impl AsRef<Path>,
-    closure: &impl Fn(String) -> String,
-) -> std::io::Result<()> {
-    std::fs::read_dir(path)?.try_for_each(move |entry| set_text(out_code, entry?, closure))
+    let mut registry = DocumentRegistry::new(Path::new(input_dir_path));
+    registry.set_document_dir(input_dir_path)?;
+    registry.check(shader_names);
+    // sorted by the routes for the binary search
+    let mut out_code = String::from("pub(crate) static DOCUMENTS: &[Document] = &[");
+    registry
+        .documents
+        .values()
+        .for_each(|document| out_code += &format!("{document},"));
+    out_code += "];";
+    std::fs::write(std::env::var("OUT_DIR").unwrap() + out_dir_path, &out_code)?;
+    match registry.valid {
+        true => Ok(()),
+        false => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "failed to register documents",
+        )),
+    }
 }
 
-fn set_text(
-    out_code: &mut String,
-    entry: DirEntry,
-    closure: &impl Fn(String) -> String,
-) -> std::io::Result<()> {
-    if entry.file_type()?.is_dir() {
-        set_text_dir(out_code, entry.path(), closure)?;
-    } else {
-        let file_entity = std::fs::read_to_string(entry.path())?;
-        let entity = closure(file_entity);
-        *out_code += &format!("({:?}, {}),", entry.path().file_stem().unwrap(), entity);
+fn out_shaders(
+    input_dir_path: &str,
+    out_dir_path: &str,
+    hash: &str,
+    validator: ShaderValidator,
+) -> std::io::Result<Vec<String>> {
+    let mut builder = ShaderBuilder {
+        root: Path::new(input_dir_path),
+        hash,
+        validator,
+        out_code: String::from("use std::collections::HashMap;pub(crate) fn get_texts()->HashMap<&'static str,RenderGraphSource>{vec!["),
+        names: Vec::new(),
+        valid: true,
+    };
+    builder.set_shader_dir(input_dir_path)?;
+    builder.out_code += "].into_iter().collect()}";
+    std::fs::write(
+        std::env::var("OUT_DIR").unwrap() + out_dir_path,
+        &builder.out_code,
+    )?;
+    match builder.valid {
+        true => Ok(builder.names),
+        false => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "failed to check shaders as desktop GLSL 4.50 without the built-ins missing in GLSL ES 3.00",
+        )),
     }
-    Ok(())
 }
 
-fn markdown2html(markdown: String) -> String {
-    use pulldown_cmark::{html, *};
-    let parser = Parser::new(&markdown);
-    let mut res = String::new();
-    html::push_html(&mut res, parser);
-    format!("{:?}", res)
+struct ShaderBuilder<'a> {
+    root: &'a Path,
+    hash: &'a str,
+    validator: ShaderValidator,
+    out_code: String,
+    /// the names of the shaders for the documents
+    names: Vec<String>,
+    valid: bool,
 }
 
-fn parse_shader(shader: String, hash: &str) -> String {
-    use std::io::{BufRead, BufReader};
-    let mut res = String::from("ShaderSource {");
-    let mut first_line = true;
-    BufReader::new(shader.as_bytes())
-        .lines()
-        .try_for_each(|line| -> std::io::Result<()> {
-            let line = line?;
-            if first_line {
-                if line.len() > 10 && &line[0..10] == "#iChannel0" {
-                    let path = Path::new(&line[12..line.len() - 1]);
-                    let mut path = path.file_name().unwrap().to_str().unwrap();
-                    if path == "selfie.jpg" {
-                        path = hash;
-                    }
-                    res += &format!("texture_url:Some(\"./{path}\"),source:\"");
-                    first_line = false;
-                    return Ok(());
-                } else {
-                    res += "texture_url:None,source:\"";
-                    first_line = false;
-                }
-            }
-            if !line.is_empty() && (line.len() < 2 || (&line[0..2] != "//" && &line[0..2] != "/*"))
-            {
-                res += line.trim();
-                res += "\n";
+impl<'a> ShaderBuilder<'a> {
+    fn set_shader_dir(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::read_dir(path)?.try_for_each(|entry| {
+            let path = entry?.path();
+            match render_graph_passes(&path) {
+                Some(passes) => self.set_render_graph(&path, passes),
+                None => self.set_shader_dir(path),
             }
-            Ok(())
         })
-        .unwrap();
-    res + "\"}"
+    }
+
+    fn set_render_graph(&mut self, path: &Path, passes: Vec<PathBuf>) -> std::io::Result<()> {
+        let pass_names = passes
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        let mut code = String::new();
+        for (idx, pass) in passes.iter().enumerate() {
+            let display_path = pass.strip_prefix(self.root).unwrap_or(pass);
+            let shader = match parse_shader(
+                std::fs::read_to_string(pass)?,
+                self.hash,
+                display_path.to_str().unwrap(),
+                (idx, &pass_names),
+            ) {
+                Ok(shader) => shader,
+                Err((line, msg)) => {
+                    eprintln!("error: {}:{line}: {msg}", pass.display());
+                    self.valid = false;
+                    continue;
+                }
+            };
+            if let Err(errors) = self.validator.validate(&shader) {
+                errors
+                    .iter()
+                    .for_each(|(line, msg)| eprintln!("error: {}:{line}: {msg}", pass.display()));
+                self.valid = false;
+            }
+            code += &format!("{shader},");
+        }
+        let name = path.file_stem().unwrap();
+        self.out_code += &format!("({name:?}, RenderGraphSource {{ passes: &[{code}] }}),");
+        self.names.push(name.to_str().unwrap().to_string());
+        Ok(())
+    }
 }
 
-// my selfie url, disable after release page
 const SELFIE_URL: &str =
     "https://drive.google.com/uc?id=1CppW3rG8--B-MdkMSoGx-o2orEcjnqRD&export=download";
 const HASH_LETTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabscefghijklmnopqrstuvwxyz0123456789";
@@ -0,0 +1,263 @@
+//! The registry of `texts/`: the markdowns with their front matter, keyed by their routes.
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The front matter between the `---` lines at the top of a markdown, a subset of YAML:
+/// `key: value` lines whose value is a string (optionally quoted), an integer or a list `[a, b]`.
+/// `date` (`YYYY-MM-DD`), `order` (an integer), `parent` and `tags` (a list) are checked,
+/// but only the keys the site uses are registered.
+#[derive(Clone, Debug, Default)]
+pub struct FrontMatter {
+    pub title: String,
+    /// the shader of the background, `default` if omitted
+    pub shader: Option<String>,
+    /// `lang` of the contents, e.g. `en` or `ja`
+    pub language: String,
+    /// the route of the parent document
+    pub parent: Option<String>,
+}
+
+/// Splits the front matter from the markdown, errors are `(line, message)`.
+pub fn parse_front_matter(markdown: &str) -> Result<(FrontMatter, &str), (usize, String)> {
+    let mut lines = markdown.split_inclusive('\n');
+    let first_line = lines.next().unwrap_or_default();
+    if first_line.trim_end() != "---" {
+        return Err((1, String::from("the front matter must start with `---`")));
+    }
+    let mut offset = first_line.len();
+    let mut entries = Vec::new();
+    for (idx, line) in lines.enumerate() {
+        offset += line.len();
+        let line_number = idx + 2;
+        let line = line.trim_end();
+        if line == "---" {
+            let front_matter = front_matter(entries, line_number)?;
+            return Ok((front_matter, &markdown[offset..]));
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(':').ok_or_else(|| {
+            (
+                line_number,
+                format!("expected `key: value`, found `{line}`"),
+            )
+        })?;
+        entries.push((line_number, key.trim(), value.trim()));
+    }
+    Err((1, String::from("the front matter is not closed by `---`")))
+}
+
+fn front_matter(
+    entries: Vec<(usize, &str, &str)>,
+    end_line: usize,
+) -> Result<FrontMatter, (usize, String)> {
+    let mut front_matter = FrontMatter::default();
+    let mut keys = HashSet::new();
+    for (line, key, value) in entries {
+        if !keys.insert(key) {
+            return Err((line, format!("duplicate key `{key}`")));
+        }
+        match key {
+            "title" => front_matter.title = string(value),
+            "shader" => front_matter.shader = Some(string(value)),
+            "language" => front_matter.language = string(value),
+            "date" => {
+                date(value).map_err(|msg| (line, msg))?;
+            }
+            "order" => {
+                value
+                    .parse::<i32>()
+                    .map_err(|_| (line, format!("`order` must be an integer, found `{value}`")))?;
+            }
+            "parent" => front_matter.parent = Some(string(value)),
+            "tags" => {
+                list(value).map_err(|msg| (line, msg))?;
+            }
+            _ => return Err((line, format!("unknown key `{key}`"))),
+        }
+    }
+    match (
+        front_matter.title.is_empty(),
+        front_matter.language.is_empty(),
+    ) {
+        (true, _) => Err((end_line, String::from("missing `title`"))),
+        (_, true) => Err((end_line, String::from("missing `language`"))),
+        _ => Ok(front_matter),
+    }
+}
+
+/// a string, optionally quoted by `"` or `'`
+fn string(value: &str) -> String {
+    let unquoted = ['"', '\'']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote));
+    unquoted.unwrap_or(value).to_string()
+}
+
+fn list(value: &str) -> Result<Vec<String>, String> {
+    let items = value
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(']'))
+        .ok_or_else(|| format!("expected a list `[a, b]`, found `{value}`"))?;
+    let items = items
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty());
+    Ok(items.map(string).collect())
+}
+
+fn date(value: &str) -> Result<(), String> {
+    let date = string(value);
+    let parts = date.split('-').collect::<Vec<_>>();
+    let valid = parts.iter().map(|part| part.len()).eq([4, 2, 2])
+        && parts
+            .iter()
+            .all(|part| part.bytes().all(|c| c.is_ascii_digit()))
+        && (1..=12).contains(&parts[1].parse::<u32>().unwrap())
+        && (1..=31).contains(&parts[2].parse::<u32>().unwrap());
+    match valid {
+        true => Ok(()),
+        false => Err(format!("`date` must be `YYYY-MM-DD`, found `{value}`")),
+    }
+}
+
+/// A markdown of `texts/` translated into HTML.
+#[derive(Clone, Debug)]
+pub struct Document {
+    pub route: String,
+    pub file: PathBuf,
+    pub front_matter: FrontMatter,
+    pub html: String,
+}
+
+/// Prints the document as the code of `contents::Document`.
+impl std::fmt::Display for Document {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let FrontMatter {
+            title,
+            shader,
+            language,
+            ..
+        } = &self.front_matter;
+        let shader = shader.as_deref().unwrap_or("default");
+        write!(
+            f,
+            "Document {{ path: {:?}, title: {title:?}, shader: {shader:?}, language: {language:?}, \
+             html: {:?} }}",
+            self.route, self.html,
+        )
+    }
+}
+
+/// The documents of a directory by their routes, which fail the build on the errors printed as `file:line`.
+pub struct DocumentRegistry<'a> {
+    root: &'a Path,
+    pub documents: BTreeMap<String, Document>,
+    pub valid: bool,
+}
+
+impl<'a> DocumentRegistry<'a> {
+    pub fn new(root: &'a Path) -> Self {
+        Self {
+            root,
+            documents: BTreeMap::new(),
+            valid: true,
+        }
+    }
+
+    pub fn set_document_dir(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::read_dir(path)?.try_for_each(|entry| {
+            let path = entry?.path();
+            match path.is_dir() {
+                true => self.set_document_dir(path),
+                false => self.set_document(path),
+            }
+        })
+    }
+
+    fn set_document(&mut self, file: PathBuf) -> std::io::Result<()> {
+        let markdown = std::fs::read_to_string(&file)?;
+        let (front_matter, markdown) = match parse_front_matter(&markdown) {
+            Ok(parsed) => parsed,
+            Err((line, msg)) => {
+                eprintln!("error: {}:{line}: {msg}", file.display());
+                self.valid = false;
+                return Ok(());
+            }
+        };
+        let route = self.route(&file);
+        if let Some(other) = self.documents.get(&route) {
+            eprintln!(
+                "error: {}: the route `{route}` is also the one of {}",
+                file.display(),
+                other.file.display(),
+            );
+            self.valid = false;
+            return Ok(());
+        }
+        let document = Document {
+            route: route.clone(),
+            html: markdown2html(markdown),
+            file,
+            front_matter,
+        };
+        self.documents.insert(route, document);
+        Ok(())
+    }
+
+    /// The path from the root without the extension,
+    /// or the directory if the file has its name, e.g. `mathematics/lecture-movie` and `mathematics`.
+    fn route(&self, file: &Path) -> String {
+        let stem = file.file_stem().unwrap().to_str().unwrap();
+        let mut route = file.with_extension("");
+        if file.parent().and_then(Path::file_name) == Some(stem.as_ref()) {
+            route.pop();
+        }
+        let route = route.strip_prefix(self.root).unwrap().components();
+        let route = route
+            .map(|component| component.as_os_str().to_str().unwrap())
+            .collect::<Vec<_>>();
+        route.join("/")
+    }
+
+    /// Checks the parents and the shaders of the documents.
+    pub fn check(&mut self, shader_names: &[String]) {
+        for document in self.documents.values() {
+            let front_matter = &document.front_matter;
+            let mut errors = Vec::new();
+            if let Some(shader) = &front_matter.shader {
+                if !shader_names.contains(shader) {
+                    errors.push(format!("no shader `{shader}`"));
+                }
+            }
+            // the chain of the parents ends within the number of the documents unless it loops
+            let mut parent = front_matter.parent.as_ref();
+            for _ in 0..self.documents.len() {
+                let Some(route) = parent else { break };
+                match self.documents.get(route) {
+                    Some(document) => parent = document.front_matter.parent.as_ref(),
+                    None => {
+                        errors.push(format!("no parent document `{route}`"));
+                        parent = None;
+                    }
+                }
+            }
+            if parent.is_some() {
+                errors.push(String::from("the parents form a loop"));
+            }
+            errors
+                .iter()
+                .for_each(|msg| eprintln!("error: {}: {msg}", document.file.display()));
+            self.valid &= errors.is_empty();
+        }
+    }
+}
+
+fn markdown2html(markdown: &str) -> String {
+    use pulldown_cmark::{html, *};
+    let parser = Parser::new(markdown);
+    let mut res = String::new();
+    html::push_html(&mut res, parser);
+    res
+}
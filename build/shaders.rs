@@ -0,0 +1,477 @@
+//! Parsing and checking of `shaders/`, shared by `build.rs` and the offline renderer `src/bin/render`.
+use std::path::{Path, PathBuf};
+
+/// Passes of a multi-pass shader directory, in the order of Shadertoy's tabs.
+/// A directory is a multi-pass shader if it contains `image.frag`.
+pub const PASS_NAMES: [&str; 5] = [
+    "buffer_a.frag",
+    "buffer_b.frag",
+    "buffer_c.frag",
+    "buffer_d.frag",
+    "image.frag",
+];
+
+/// the passes of the render graph at `path`, or `None` if it is a directory of shaders
+pub fn render_graph_passes(path: &Path) -> Option<Vec<PathBuf>> {
+    if !path.is_dir() {
+        Some(vec![path.to_path_buf()])
+    } else if path.join("image.frag").exists() {
+        let passes = PASS_NAMES
+            .iter()
+            .map(|name| path.join(name))
+            .filter(|path| path.exists())
+            .collect();
+        Some(passes)
+    } else {
+        None
+    }
+}
+
+pub const CHANNEL_COUNT: usize = 4;
+
+/// suffixes of the six faces of a cubemap, `{}` in the path is replaced by them
+pub const CUBE_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+pub enum ChannelInput {
+    Texture(String),
+    CubeFaces([String; 6]),
+    CubeCross(String),
+    VolumeBinary(String),
+    VolumeTiles(String, [u32; 2]),
+    Buffer(usize),
+    Keyboard,
+    Video(String),
+    Webcam,
+    Audio(String),
+}
+
+impl ChannelInput {
+    pub fn sampler_type(&self) -> &'static str {
+        match self {
+            Self::CubeFaces(_) | Self::CubeCross(_) => "samplerCube",
+            Self::VolumeBinary(_) | Self::VolumeTiles(..) => "sampler3D",
+            _ => "sampler2D",
+        }
+    }
+}
+
+impl std::fmt::Display for ChannelInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Texture(url) => write!(f, "ChannelInput::Texture({url:?})"),
+            Self::CubeFaces(urls) => write!(f, "ChannelInput::CubeFaces({urls:?})"),
+            Self::CubeCross(url) => write!(f, "ChannelInput::CubeCross({url:?})"),
+            Self::VolumeBinary(url) => write!(f, "ChannelInput::VolumeBinary({url:?})"),
+            Self::VolumeTiles(url, tiles) => {
+                write!(f, "ChannelInput::VolumeTiles({url:?}, {tiles:?})")
+            }
+            Self::Buffer(idx) => write!(f, "ChannelInput::Buffer({idx})"),
+            Self::Keyboard => write!(f, "ChannelInput::Keyboard"),
+            Self::Video(url) => write!(f, "ChannelInput::Video({url:?})"),
+            Self::Webcam => write!(f, "ChannelInput::Webcam"),
+            Self::Audio(url) => write!(f, "ChannelInput::Audio({url:?})"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    Nearest,
+    Linear,
+    Mipmap,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+/// `#iChannelN "<input>"` and the optional sampler settings
+/// `#iChannelN::Filter "nearest|linear|mipmap"`, `#iChannelN::Wrap "clamp|repeat|mirror"`
+/// and `#iChannelN::VFlip "true|false"`.
+///
+/// `#iChannelN::Type "cube"` makes the input a cubemap, from six faces if the path contains `{}`,
+/// otherwise from a horizontal cross layout image.
+/// `#iChannelN::Type "3d"` makes the input a volume, from Shadertoy's binary format if the path ends with `.bin`,
+/// otherwise from an image of slices tiled by `#iChannelN::Tiles "<columns>x<rows>"`.
+#[derive(Default)]
+pub struct ChannelSource {
+    pub input: Option<ChannelInput>,
+    filter: Option<Filter>,
+    wrap: Option<Wrap>,
+    vflip: Option<bool>,
+    ty: Option<&'static str>,
+    tiles: Option<[u32; 2]>,
+    /// the line of the input, or of the first setting without it, for the errors of `resolve`
+    line: usize,
+}
+
+impl ChannelSource {
+    fn set_option(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match (key, value) {
+            ("Filter", "nearest") => self.filter = Some(Filter::Nearest),
+            ("Filter", "linear") => self.filter = Some(Filter::Linear),
+            ("Filter", "mipmap") => self.filter = Some(Filter::Mipmap),
+            ("Wrap", "clamp") => self.wrap = Some(Wrap::Clamp),
+            ("Wrap", "repeat") => self.wrap = Some(Wrap::Repeat),
+            ("Wrap", "mirror") => self.wrap = Some(Wrap::Mirror),
+            ("VFlip", "true") => self.vflip = Some(true),
+            ("VFlip", "false") => self.vflip = Some(false),
+            ("Type", "2d") => self.ty = Some("2d"),
+            ("Type", "cube") => self.ty = Some("cube"),
+            ("Type", "3d") => self.ty = Some("3d"),
+            ("Tiles", _) => {
+                let tiles = value
+                    .split_once('x')
+                    .and_then(|(x, y)| Some([x.parse().ok()?, y.parse().ok()?]));
+                self.tiles = Some(tiles.ok_or_else(|| format!("invalid tiles \"{value}\""))?);
+            }
+            _ => return Err(format!("unknown channel setting {key} \"{value}\"")),
+        }
+        Ok(())
+    }
+
+    /// applies `Type` and `Tiles` to the input
+    fn resolve(&mut self) -> Result<(), String> {
+        let input = match self.input.take() {
+            Some(input) => input,
+            None if self.filter.is_some() || self.wrap.is_some() || self.vflip.is_some() => {
+                return Err(String::from("sampler settings without input"))
+            }
+            None => return Ok(()),
+        };
+        self.input = Some(match (input, self.ty.unwrap_or("2d"), self.tiles) {
+            (input, "2d", None) => input,
+            (ChannelInput::Texture(url), "cube", None) => match url.contains("{}") {
+                true => ChannelInput::CubeFaces(CUBE_FACES.map(|face| url.replace("{}", face))),
+                false => ChannelInput::CubeCross(url),
+            },
+            (ChannelInput::Texture(url), "3d", None) if url.ends_with(".bin") => {
+                ChannelInput::VolumeBinary(url)
+            }
+            (ChannelInput::Texture(url), "3d", Some(tiles)) => {
+                ChannelInput::VolumeTiles(url, tiles)
+            }
+            (ChannelInput::Texture(_), "3d", None) => {
+                return Err(String::from("3d textures from images require Tiles"))
+            }
+            (_, ty, _) => return Err(format!("invalid input for the type {ty}")),
+        });
+        Ok(())
+    }
+
+    /// the filter, the wrap and the vflip, filled by the defaults of Shadertoy
+    pub fn sampler(&self) -> Option<(Filter, Wrap, bool)> {
+        let (filter, wrap, vflip) = match self.input.as_ref()? {
+            ChannelInput::Buffer(_) => (Filter::Linear, Wrap::Clamp, false),
+            ChannelInput::CubeFaces(_) | ChannelInput::CubeCross(_) => {
+                (Filter::Mipmap, Wrap::Clamp, false)
+            }
+            ChannelInput::VolumeBinary(_) | ChannelInput::VolumeTiles(..) => {
+                (Filter::Mipmap, Wrap::Repeat, false)
+            }
+            ChannelInput::Texture(_) => (Filter::Mipmap, Wrap::Repeat, true),
+            ChannelInput::Keyboard => (Filter::Nearest, Wrap::Clamp, false),
+            ChannelInput::Audio(_) => (Filter::Linear, Wrap::Clamp, false),
+            ChannelInput::Video(_) | ChannelInput::Webcam => (Filter::Linear, Wrap::Clamp, true),
+        };
+        Some((
+            self.filter.unwrap_or(filter),
+            self.wrap.unwrap_or(wrap),
+            self.vflip.unwrap_or(vflip),
+        ))
+    }
+}
+
+impl std::fmt::Display for ChannelSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.input, self.sampler()) {
+            (Some(input), Some((filter, wrap, vflip))) => write!(
+                f,
+                "Some(ChannelSource {{ input: {input}, sampler: SamplerSettings {{ filter: Filter::{filter:?}, wrap: Wrap::{wrap:?}, vflip: {vflip} }} }})",
+            ),
+            _ => write!(f, "None"),
+        }
+    }
+}
+
+pub struct ShaderSource {
+    pub path: String,
+    pub channels: [ChannelSource; CHANNEL_COUNT],
+    pub source: String,
+    /// the line numbers in the `.frag` of each line of `source`
+    pub source_lines: Vec<usize>,
+}
+
+impl std::fmt::Display for ShaderSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            path,
+            channels,
+            source,
+            source_lines,
+        } = self;
+        let channels = channels
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            f,
+            "ShaderSource {{ path: {path:?}, channels: [{channels}], source: {source:?}, source_lines: &{source_lines:?} }}",
+        )
+    }
+}
+
+/// `pass` is the index of the shader in the render graph and the file names of all passes,
+/// which can be referred from `#iChannel0 "file://buffer_a.frag"` or `#iChannel0 "self"`.
+/// `#iChannel0 "keyboard"` reads the key states, `#iChannel0 "webcam"` the camera
+/// and `#iChannel0 "audio:music.mp3"` the spectrum and the waveform of the track.
+/// Returns the line number and the message if a channel directive is invalid.
+pub fn parse_shader(
+    shader: String,
+    hash: &str,
+    path: &str,
+    pass: (usize, &[&str]),
+) -> Result<ShaderSource, (usize, String)> {
+    let mut res = ShaderSource {
+        path: path.to_string(),
+        channels: Default::default(),
+        source: String::new(),
+        source_lines: Vec::new(),
+    };
+    shader.lines().enumerate().try_for_each(|(idx, line)| {
+        if let Some(directive) = line.strip_prefix("#iChannel") {
+            parse_channel_directive(&mut res.channels, directive, idx + 1, hash, pass)
+                .map_err(|msg| (idx + 1, msg))?;
+        } else if !line.is_empty()
+            && (line.len() < 2 || (&line[0..2] != "//" && &line[0..2] != "/*"))
+        {
+            res.source += line.trim();
+            res.source += "\n";
+            res.source_lines.push(idx + 1);
+        }
+        Ok(())
+    })?;
+    res.channels
+        .iter_mut()
+        .enumerate()
+        .try_for_each(|(idx, channel)| {
+            channel
+                .resolve()
+                .map_err(|msg| (channel.line, format!("iChannel{idx}: {msg}")))
+        })?;
+    Ok(res)
+}
+
+/// files played as videos instead of loaded as images
+const VIDEO_EXTENSIONS: [&str; 4] = [".mp4", ".webm", ".ogv", ".mov"];
+
+/// `directive` is the line `line` without the leading `#iChannel`, e.g. `0 "file://buffer_a.frag"` or `1::Wrap "clamp"`.
+fn parse_channel_directive(
+    channels: &mut [ChannelSource; CHANNEL_COUNT],
+    directive: &str,
+    line: usize,
+    hash: &str,
+    pass: (usize, &[&str]),
+) -> Result<(), String> {
+    let (head, value) = directive
+        .split_once(' ')
+        .map(|(head, value)| (head, value.trim().trim_matches('"')))
+        .ok_or_else(|| format!("invalid channel directive: #iChannel{directive}"))?;
+    let (idx, key) = head.split_once("::").unwrap_or((head, ""));
+    let channel = idx
+        .parse::<usize>()
+        .ok()
+        .and_then(|idx| channels.get_mut(idx))
+        .ok_or_else(|| format!("channel index must be 0 to {}", CHANNEL_COUNT - 1))?;
+    if !key.is_empty() {
+        if channel.line == 0 {
+            channel.line = line;
+        }
+        return channel.set_option(key, value);
+    }
+    channel.line = line;
+    let path = value
+        .trim_start_matches("audio:")
+        .trim_start_matches("file://");
+    let path = Path::new(path);
+    let mut path = path.file_name().unwrap_or_default().to_str().unwrap();
+    let buffer = pass.1.iter().position(|name| *name == path);
+    channel.input = Some(match (value, buffer) {
+        ("self", _) => ChannelInput::Buffer(pass.0),
+        ("keyboard", _) => ChannelInput::Keyboard,
+        ("webcam", _) => ChannelInput::Webcam,
+        _ if value.starts_with("audio:") => ChannelInput::Audio(format!("./{path}")),
+        (_, Some(buffer)) => ChannelInput::Buffer(buffer),
+        _ if VIDEO_EXTENSIONS.iter().any(|ext| path.ends_with(ext)) => {
+            ChannelInput::Video(format!("./{path}"))
+        }
+        _ => {
+            if path == "selfie.jpg" {
+                path = hash;
+            }
+            ChannelInput::Texture(format!("./{path}"))
+        }
+    });
+    Ok(())
+}
+
+// the prefix prepended to every shader at runtime, see `webgl::prepare_program`
+pub const FRAGMENT_SHADER_PREFIX: &str = "./src/background/prefix.frag";
+
+/// Checks shaders with naga's GLSL frontend, as desktop GLSL 4.50 and not as GLSL ES 3.00.
+///
+/// naga only reads desktop GLSL 4.50, so the GLSL ES 3.00 prefix is rewritten with the same uniforms:
+/// plain uniforms are gathered into a uniform block, samplers are split into texture/sampler pairs
+/// and `main` is moved behind the shader body.
+/// Only the frontend (syntax, names and types) is checked: naga's IR validator rejects valid ES code
+/// such as `const int A[] = int[](...)`.
+/// The built-ins and types missing in ES 3.00 are rejected before, see `DESKTOP_ONLY`,
+/// but the rules of ES such as no implicit conversions from `int` to `float` are not checked.
+pub struct ShaderValidator {
+    header: String,
+    footer: String,
+    /// the first binding for the channels of each shader
+    binding: u32,
+}
+
+impl ShaderValidator {
+    pub fn new(prefix: &str) -> Self {
+        let mut header = String::from("#version 450\n");
+        let mut footer = String::from("\n");
+        let mut block = String::new();
+        let mut binding = 1;
+        // skip `#version 300 es`
+        let body = prefix.split_once('\n').map_or(prefix, |(_, body)| body);
+        split_statements(body).into_iter().for_each(|statement| {
+            let words = statement.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                ["uniform", ty, name] if ty.starts_with("sampler") => {
+                    header += &sampler_declaration(binding, ty, name);
+                    binding += 2;
+                }
+                ["uniform", ty, name] => block += &format!("{ty} {name};"),
+                ["out", ..] => header += &format!("layout(location=0) {statement};\n"),
+                ["void", main, ..] if main.starts_with("main(") => footer += &statement,
+                // naga does not resolve forward declarations of functions with `out` parameters
+                ["void", ..] => {}
+                _ => header += &format!("{statement};\n"),
+            }
+        });
+        header += &format!("layout(binding=0) uniform Uniforms{{{block}}};\n");
+        Self {
+            header,
+            footer,
+            binding,
+        }
+    }
+
+    /// Returns pairs of the line number in the original `.frag` and the message.
+    pub fn validate(&self, shader: &ShaderSource) -> Result<(), Vec<(usize, String)>> {
+        let errors = desktop_only_errors(shader);
+        match errors.is_empty() {
+            true => self.parse(shader).map(|_| ()),
+            false => Err(errors),
+        }
+    }
+
+    /// Translates the shader into naga's IR, which is read by the offline renderer.
+    pub fn parse(&self, shader: &ShaderSource) -> Result<naga::Module, Vec<(usize, String)>> {
+        use naga::front::glsl::{Frontend, Options};
+        // the types of channels depend on the shader
+        let header = shader.channels.iter().enumerate().fold(
+            self.header.clone(),
+            |header, (idx, channel)| {
+                let ty = channel
+                    .input
+                    .as_ref()
+                    .map_or("sampler2D", ChannelInput::sampler_type);
+                let binding = self.binding + 2 * idx as u32;
+                header + &sampler_declaration(binding, ty, &format!("iChannel{idx}"))
+            },
+        );
+        let source = format!("{header}{}{}", shader.source, self.footer);
+        let header_lines = header.matches('\n').count();
+        let options = Options::from(naga::ShaderStage::Fragment);
+        Frontend::default()
+            .parse(&options, &source)
+            .map_err(|errors| {
+                errors
+                    .into_iter()
+                    .map(|e| {
+                        let line = e.meta.location(&source).line_number as usize;
+                        let line = line
+                            .checked_sub(header_lines + 1)
+                            .and_then(|idx| shader.source_lines.get(idx))
+                            .copied()
+                            .unwrap_or(0);
+                        (line, e.kind.to_string())
+                    })
+                    .collect()
+            })
+    }
+}
+
+/// the built-ins and types of desktop GLSL 4.50 which GLSL ES 3.00 does not have
+const DESKTOP_ONLY: &str = "double dvec2 dvec3 dvec4 dmat2 dmat3 dmat4 \
+    sampler1D sampler1DShadow sampler2DRect samplerBuffer \
+    texture1D texture2D texture3D textureCube texture2DLod textureCubeLod shadow2D \
+    gl_FragColor gl_FragData textureGather textureGatherOffset textureGatherOffsets \
+    textureQueryLod textureQueryLevels textureSamples fma frexp ldexp \
+    bitfieldExtract bitfieldInsert bitfieldReverse bitCount findLSB findMSB \
+    uaddCarry usubBorrow umulExtended imulExtended \
+    packUnorm4x8 packSnorm4x8 unpackUnorm4x8 unpackSnorm4x8 packDouble2x32 unpackDouble2x32 \
+    dFdxFine dFdyFine dFdxCoarse dFdyCoarse fwidthFine fwidthCoarse";
+
+/// the uses of `DESKTOP_ONLY`, which naga accepts but the browsers do not
+fn desktop_only_errors(shader: &ShaderSource) -> Vec<(usize, String)> {
+    let lines = shader.source.lines().zip(&shader.source_lines);
+    lines
+        .flat_map(|(code, line)| {
+            let code = code.split("//").next().unwrap_or_default();
+            code.split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .filter(|word| DESKTOP_ONLY.split_whitespace().any(|name| name == *word))
+                .map(move |word| (*line, format!("`{word}` is not available in GLSL ES 3.00")))
+        })
+        .collect()
+}
+
+/// a combined sampler declared as a pair of a texture and a sampler
+fn sampler_declaration(binding: u32, ty: &str, name: &str) -> String {
+    let texture = ty.replace("sampler", "texture");
+    format!(
+        "layout(binding={binding}) uniform {texture} {name}_texture;\n\
+         layout(binding={}) uniform sampler {name}_sampler;\n\
+         #define {name} {ty}({name}_texture,{name}_sampler)\n",
+        binding + 1,
+    )
+}
+
+/// Splits GLSL into top-level statements, separated by `;` or closing braces.
+fn split_statements(code: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    code.chars().for_each(|c| match c {
+        ';' if depth == 0 => res.push(std::mem::take(&mut current)),
+        '{' => {
+            depth += 1;
+            current.push(c);
+        }
+        '}' => {
+            depth -= 1;
+            current.push(c);
+            if depth == 0 {
+                res.push(std::mem::take(&mut current));
+            }
+        }
+        _ => current.push(c),
+    });
+    res.into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
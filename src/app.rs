@@ -1,48 +1,95 @@
 use crate::*;
+use router::{Route, RouteListener};
 
 pub struct App {
     from_mobile: bool,
+    /// `BackGround` stays mounted across the routes and cross-fades to the shader of the next document
+    shader_name: String,
+    audio_playing: bool,
+    route: Route,
+    _route_listener: RouteListener,
+}
+
+pub enum Msg {
+    Route(Route),
+    AudioPlaying(bool),
 }
 
 impl Component for App {
-    type Message = ();
+    type Message = Msg;
     type Properties = ();
 
-    fn create(_: &Context<Self>) -> Self {
+    fn create(ctx: &Context<Self>) -> Self {
         let from_mobile = from_mobile();
-        set_title();
+        let route = Route::from_location();
+        set_title(&route);
         set_html_class(from_mobile);
-        Self { from_mobile }
+        let query = Query::from_location();
+        Self {
+            from_mobile,
+            shader_name: shader_name(&route, &query),
+            audio_playing: false,
+            route,
+            _route_listener: RouteListener::set(ctx.link().callback(Msg::Route)),
+        }
     }
 
-    fn view(&self, _: &Context<Self>) -> Html {
+    fn update(&mut self, _: &Context<Self>, msg: Msg) -> bool {
+        let route = match msg {
+            Msg::Route(route) if route == self.route => return false,
+            Msg::Route(route) => route,
+            Msg::AudioPlaying(playing) => {
+                self.audio_playing = playing;
+                return true;
+            }
+        };
+        set_title(&route);
+        gloo::utils::window().scroll_to_with_x_and_y(0.0, 0.0);
+        self.shader_name = shader_name(&route, &Query::from_location());
+        self.route = route;
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
         let query = Query::from_location();
-        if query.doc.as_deref() == Some("none") {
-            let shader_name = query.shader.unwrap_or_else(|| String::from("default"));
-            html! { <background::BackGround shader_name={ shader_name } /> }
-        } else {
-            let doc_name = query.doc.clone();
-            let shader_name = query
-                .shader
-                .or(query.doc)
-                .unwrap_or_else(|| String::from("default"));
-            let rarefaction = !self.from_mobile;
-            html! {
-                <>
+        let standalone = self.route == Route::Background;
+        let audio_switch = background::has_audio(&self.shader_name);
+        let doc_path = self.route.doc_path().map(String::from);
+        let rarefaction = !self.from_mobile;
+        html! {
+            <>
+            // the first child keeps the background mounted when the contents change
+            <background::BackGround shader_name={ self.shader_name.clone() } standalone={ standalone }
+                edit={ standalone && query.edit } audio_playing={ self.audio_playing }
+                time={ query.time } speed={ query.speed } paused={ query.paused } seed={ query.seed }
+                fade={ query.fade } worker={ query.worker } />
+            if !standalone {
                 <navbar::NavBar rarefaction={ rarefaction } />
-                <contents::Contents doc_name={ doc_name } />
+                <contents::Contents doc_path={ doc_path } />
                 <div class="copyright">{ "Copyright © 2022 YOSHINORI TANIMURA, All right reserved." }</div>
-                <iframe class="background" src={ format!("./index.html?doc=none&shader={shader_name}") } />
-                </>
             }
+            if audio_switch { <audioswitch::AudioSwitch on_toggle={ ctx.link().callback(Msg::AudioPlaying) } /> }
+            </>
         }
     }
 }
 
+/// The parameters of the query, the page is given by `Route`.
 #[derive(Clone, Debug)]
 pub struct Query {
-    pub doc: Option<String>,
     pub shader: Option<String>,
+    /// `edit=1` shows the shader editor over the standalone background
+    pub edit: bool,
+    /// `t`: `iTime` of the first frame
+    pub time: Option<f64>,
+    pub speed: Option<f64>,
+    /// `paused=1`
+    pub paused: bool,
+    pub seed: Option<u32>,
+    /// the seconds of the cross-fade between the shaders, 0 switches at once
+    pub fade: Option<f64>,
+    /// `worker=1` renders the background of the documents in a worker
+    pub worker: bool,
 }
 
 impl Query {
@@ -52,12 +99,29 @@ impl Query {
         let raw_query = location.search().expect_throw("failed to get query");
         let qstr = QString::from(raw_query.as_str());
         Query {
-            doc: qstr.get("doc").map(String::from),
             shader: qstr.get("shader").map(String::from),
+            edit: qstr.get("edit") == Some("1"),
+            time: qstr.get("t").and_then(parse_number),
+            speed: qstr.get("speed").and_then(parse_number),
+            paused: qstr.get("paused") == Some("1"),
+            seed: qstr.get("seed").and_then(|seed| seed.parse().ok()),
+            fade: qstr.get("fade").and_then(parse_number),
+            worker: qstr.get("worker") == Some("1"),
         }
     }
 }
 
+fn parse_number(value: &str) -> Option<f64> {
+    value.parse().ok().filter(|value: &f64| value.is_finite())
+}
+
+/// `?shader=` or the shader of the document
+fn shader_name(route: &Route, query: &Query) -> String {
+    let document_shader = || route.document().map(|document| document.shader);
+    let shader_name = query.shader.as_deref().or_else(document_shader);
+    shader_name.unwrap_or("default").to_string()
+}
+
 fn from_mobile() -> bool {
     let agent: String = gloo::utils::window()
         .navigator()
@@ -67,10 +131,11 @@ fn from_mobile() -> bool {
     agent.contains("android") || agent.contains("iphone") || agent.contains("ipad")
 }
 
-fn set_title() {
-    if let Some(doc) = Query::from_location().doc {
-        let document = gloo::utils::document();
-        document.set_title(&format!("yotabaito: {doc}"));
+fn set_title(route: &Route) {
+    let document = gloo::utils::document();
+    match route.document() {
+        Some(doc) => document.set_title(&format!("yotabaito: {}", doc.title)),
+        None => document.set_title("yotabaito"),
     }
 }
 
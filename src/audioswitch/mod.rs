@@ -0,0 +1,43 @@
+use crate::*;
+
+/// Plays or mutes the audio channels of the background, which cannot start without a click.
+#[derive(Debug, Default)]
+pub struct AudioSwitch {
+    playing: bool,
+}
+
+pub enum Msg {
+    Toggle,
+}
+
+#[derive(Clone, Debug, PartialEq, Properties)]
+pub struct Props {
+    /// whether the audio is playing, for `BackGround::audio_playing`
+    pub on_toggle: Callback<bool>,
+}
+
+impl Component for AudioSwitch {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_: &Context<Self>) -> Self {
+        Default::default()
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let label = match self.playing {
+            true => "♪ mute",
+            false => "♪ play",
+        };
+        html! {
+            <div class="audioswitch" onclick={ ctx.link().callback(|_| Msg::Toggle) }>{ label }</div>
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Msg) -> bool {
+        let Msg::Toggle = msg;
+        self.playing = !self.playing;
+        ctx.props().on_toggle.emit(self.playing);
+        true
+    }
+}
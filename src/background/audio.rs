@@ -0,0 +1,103 @@
+use super::channel;
+use super::*;
+use WebGl2RenderingContext as GL;
+
+/// the number of FFT bins and waveform samples, the width of the texture
+const SAMPLE_COUNT: usize = 512;
+
+/// A music channel in the layout of Shadertoy, a 512x2 texture:
+/// the first row is the spectrum and the second row is the waveform.
+///
+/// The track waits for `set_playing` since browsers block audible autoplay.
+#[derive(Clone, Debug)]
+pub struct AudioTexture {
+    texture: WebGlTexture,
+    audio: HtmlAudioElement,
+    context: AudioContext,
+    analyser: AnalyserNode,
+    gain: GainNode,
+}
+
+impl AudioTexture {
+    /// Routes a looped track through an analyser to the speakers.
+    pub fn new(gl: &GL, url: &str) -> Option<AudioTexture> {
+        let audio = HtmlAudioElement::new_with_src(url).ok()?;
+        audio.set_loop(true);
+        let context = AudioContext::new().ok()?;
+        let source = context.create_media_element_source(&audio).ok()?;
+        let analyser = context.create_analyser().ok()?;
+        analyser.set_fft_size(SAMPLE_COUNT as u32 * 2);
+        let gain = context.create_gain().ok()?;
+        source.connect_with_audio_node(&analyser).ok()?;
+        analyser.connect_with_audio_node(&gain).ok()?;
+        gain.connect_with_audio_node(&context.destination()).ok()?;
+        Some(AudioTexture {
+            texture: gl.create_texture()?,
+            audio,
+            context,
+            analyser,
+            gain,
+        })
+    }
+
+    /// Starts the track on the first call, and mutes or unmutes it after that.
+    pub fn set_playing(&self, playing: bool) {
+        self.gain.gain().set_value(match playing {
+            true => 1.0,
+            false => 0.0,
+        });
+        if playing && self.audio.paused() {
+            let promises = [self.context.resume(), self.audio.play()];
+            promises.into_iter().flatten().for_each(|promise| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Err(e) = wasm_bindgen_futures::JsFuture::from(promise).await {
+                        gloo::console::error!(format!("failed to play audio: {e:?}"));
+                    }
+                });
+            });
+        }
+    }
+
+    /// Stops the track and releases the audio device.
+    pub fn stop(&self) {
+        let _ = self.audio.pause();
+        let _ = self.context.close();
+    }
+
+    pub fn texture(&self) -> &WebGlTexture {
+        &self.texture
+    }
+
+    pub fn resolution(&self) -> [f32; 3] {
+        [SAMPLE_COUNT as f32, 2.0, 1.0]
+    }
+
+    /// `iChannelTime`: the playback position
+    pub fn time(&self) -> f32 {
+        self.audio.current_time() as f32
+    }
+
+    /// Uploads the spectrum and the waveform of the current frame.
+    pub fn upload(&self, gl: &GL) {
+        let mut texels = [0; SAMPLE_COUNT * 2];
+        let (spectrum, waveform) = texels.split_at_mut(SAMPLE_COUNT);
+        self.analyser.get_byte_frequency_data(spectrum);
+        self.analyser.get_byte_time_domain_data(waveform);
+        let (internal_format, format) = channel::single_channel_format(gl);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.texture));
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            internal_format,
+            SAMPLE_COUNT as i32,
+            2,
+            0,
+            format,
+            GL::UNSIGNED_BYTE,
+            Some(&texels),
+        )
+        .unwrap_or_else(|e| panic!("{e:?}"));
+        gl.bind_texture(GL::TEXTURE_2D, None);
+    }
+}
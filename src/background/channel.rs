@@ -0,0 +1,536 @@
+use super::audio::AudioTexture;
+use super::video::VideoTexture;
+use super::webgl;
+use super::worker;
+use super::*;
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use WebGl2RenderingContext as GL;
+
+/// the number of `iChannelN` uniforms
+pub const CHANNEL_COUNT: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ChannelInput {
+    Texture(&'static str),
+    /// six images in the order of +X, -X, +Y, -Y, +Z, -Z
+    CubeFaces([&'static str; 6]),
+    /// one image of a horizontal cross, 4 faces wide and 3 faces high
+    CubeCross(&'static str),
+    /// a volume in Shadertoy's binary format
+    VolumeBinary(&'static str),
+    /// a volume sliced into an image of columns x rows tiles
+    VolumeTiles(&'static str, [u32; 2]),
+    /// output of the pass with this index in the render graph
+    Buffer(usize),
+    /// key states of the shared `Keyboard` of the render graph
+    Keyboard,
+    /// a looped video file
+    Video(&'static str),
+    Webcam,
+    /// FFT and waveform of a looped track
+    Audio(&'static str),
+}
+
+impl ChannelInput {
+    /// the type of `iChannelN` in GLSL
+    pub fn sampler_type(&self) -> &'static str {
+        match self {
+            ChannelInput::CubeFaces(_) | ChannelInput::CubeCross(_) => "samplerCube",
+            ChannelInput::VolumeBinary(_) | ChannelInput::VolumeTiles(..) => "sampler3D",
+            _ => "sampler2D",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelSource {
+    pub input: ChannelInput,
+    pub sampler: SamplerSettings,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerSettings {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    /// flips images on upload, no effect on buffers
+    pub vflip: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+    Mipmap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+#[derive(Clone, Debug)]
+pub enum Channel {
+    Texture(TextureInfo),
+    Buffer(usize),
+    Keyboard,
+    Video(VideoTexture),
+    Audio(AudioTexture),
+}
+
+#[derive(Clone, Debug)]
+pub struct ChannelBinding {
+    pub channel: Channel,
+    /// `None` for WebGL1, which sets `settings` to the texture instead
+    pub sampler: Option<WebGlSampler>,
+    pub settings: SamplerSettings,
+    /// buffers have to regenerate mipmaps every frame
+    pub mipmap: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextureInfo {
+    texture: WebGlTexture,
+    /// `TEXTURE_2D`, `TEXTURE_CUBE_MAP` or `TEXTURE_3D`
+    target: u32,
+    /// the depth is 1 except for volumes
+    resolution: Arc<[AtomicU32; 3]>,
+}
+
+impl TextureInfo {
+    fn new(gl: &GL, target: u32) -> Option<TextureInfo> {
+        Some(TextureInfo {
+            texture: gl.create_texture()?,
+            target,
+            resolution: Arc::new([AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(1)]),
+        })
+    }
+
+    pub fn texture(&self) -> &WebGlTexture {
+        &self.texture
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    pub fn resolution(&self) -> [f32; 3] {
+        [
+            self.resolution[0].load(Ordering::SeqCst) as f32,
+            self.resolution[1].load(Ordering::SeqCst) as f32,
+            self.resolution[2].load(Ordering::SeqCst) as f32,
+        ]
+    }
+
+    fn set_resolution(&self, resolution: [u32; 3]) {
+        (0..3).for_each(|i| self.resolution[i].store(resolution[i], Ordering::SeqCst));
+    }
+}
+
+pub fn bind_channel(gl: &GL, source: ChannelSource) -> Option<ChannelBinding> {
+    let ChannelSource { input, sampler } = source;
+    // WebGL1 has no 3D textures nor `UNPACK_ROW_LENGTH` to cut out the faces
+    let webgl2_only = matches!(
+        input,
+        ChannelInput::CubeCross(_) | ChannelInput::VolumeBinary(_) | ChannelInput::VolumeTiles(..)
+    );
+    if webgl2_only && webgl::is_webgl1(gl) {
+        gloo::console::warn!(format!("{input:?} requires WebGL2"));
+        return None;
+    }
+    let in_worker = worker::in_worker();
+    if in_worker && !worker::supports_input(input) {
+        gloo::console::warn!(format!("{input:?} is not supported in the worker"));
+        return None;
+    }
+    let (vflip, mipmap) = (sampler.vflip, sampler.filter == Filter::Mipmap);
+    let channel = match input {
+        ChannelInput::Texture(url) if in_worker => {
+            Channel::Texture(set_texture_bitmap(gl, url, sampler.vflip)?)
+        }
+        ChannelInput::Texture(url) => Channel::Texture(set_texture(gl, url, sampler.vflip)?),
+        ChannelInput::CubeFaces(urls) => Channel::Texture(set_cube_faces(gl, urls, sampler.vflip)?),
+        ChannelInput::CubeCross(url) => Channel::Texture(set_cube_cross(gl, url)?),
+        ChannelInput::VolumeBinary(url) => Channel::Texture(set_volume_binary(gl, url)?),
+        ChannelInput::VolumeTiles(url, tiles) => {
+            Channel::Texture(set_volume_tiles(gl, url, tiles)?)
+        }
+        ChannelInput::Buffer(idx) => Channel::Buffer(idx),
+        ChannelInput::Keyboard => Channel::Keyboard,
+        ChannelInput::Video(url) => Channel::Video(VideoTexture::from_url(gl, url, vflip, mipmap)?),
+        ChannelInput::Webcam => Channel::Video(VideoTexture::from_webcam(gl, vflip, mipmap)?),
+        ChannelInput::Audio(url) => Channel::Audio(AudioTexture::new(gl, url)?),
+    };
+    Some(ChannelBinding {
+        channel,
+        sampler: create_sampler(gl, sampler),
+        settings: sampler,
+        mipmap,
+    })
+}
+
+/// `TEXTURE_MIN_FILTER`, `TEXTURE_MAG_FILTER` and the wraps of S, T and R
+fn sampler_parameters(settings: SamplerSettings) -> [(u32, u32); 5] {
+    let (min_filter, mag_filter) = match settings.filter {
+        Filter::Nearest => (GL::NEAREST, GL::NEAREST),
+        Filter::Linear => (GL::LINEAR, GL::LINEAR),
+        Filter::Mipmap => (GL::LINEAR_MIPMAP_NEAREST, GL::LINEAR),
+    };
+    let wrap = match settings.wrap {
+        Wrap::Clamp => GL::CLAMP_TO_EDGE,
+        Wrap::Repeat => GL::REPEAT,
+        Wrap::Mirror => GL::MIRRORED_REPEAT,
+    };
+    [
+        (GL::TEXTURE_MIN_FILTER, min_filter),
+        (GL::TEXTURE_MAG_FILTER, mag_filter),
+        (GL::TEXTURE_WRAP_S, wrap),
+        (GL::TEXTURE_WRAP_T, wrap),
+        (GL::TEXTURE_WRAP_R, wrap),
+    ]
+}
+
+/// `None` for WebGL1, see `set_texture_parameters`
+fn create_sampler(gl: &GL, settings: SamplerSettings) -> Option<WebGlSampler> {
+    if webgl::is_webgl1(gl) {
+        return None;
+    }
+    let sampler = gl
+        .create_sampler()
+        .expect_throw("failed to create sampler pointer");
+    sampler_parameters(settings)
+        .iter()
+        .for_each(|(pname, param)| gl.sampler_parameteri(&sampler, *pname, *param as i32));
+    Some(sampler)
+}
+
+/// Sets the settings to the texture bound to `target` instead of a sampler for WebGL1.
+/// A texture without mipmaps and repeats is incomplete and samples black,
+/// so they fall back to `Linear` and `Clamp` for non power of two sizes.
+pub fn set_texture_parameters(gl: &GL, target: u32, settings: SamplerSettings, size: [u32; 2]) {
+    let mut settings = settings;
+    if !power_of_two(gl, size) {
+        if settings.filter == Filter::Mipmap {
+            settings.filter = Filter::Linear;
+        }
+        settings.wrap = Wrap::Clamp;
+    }
+    // no 3D textures in WebGL1
+    sampler_parameters(settings)[..4]
+        .iter()
+        .for_each(|(pname, param)| gl.tex_parameteri(target, *pname, *param as i32));
+}
+
+/// the internal format and the format of textures with a single channel
+pub fn single_channel_format(gl: &GL) -> (i32, u32) {
+    match webgl::is_webgl1(gl) {
+        true => (GL::LUMINANCE as i32, GL::LUMINANCE),
+        false => (GL::R8 as i32, GL::RED),
+    }
+}
+
+/// Calls `on_load` with the image once it is loaded.
+fn load_image(url: &str, on_load: impl FnOnce(&HtmlImageElement) + 'static) {
+    let image = HtmlImageElement::new().expect_throw("failed to create Image element");
+    let cloned_image = image.clone();
+    gloo::events::EventListener::once(&image, "load", move |_| on_load(&cloned_image)).forget();
+    image.set_src(url);
+}
+
+/// whether the size allows mipmaps and repeats, which WebGL1 supports only for power of two sizes
+pub fn power_of_two(gl: &GL, [width, height]: [u32; 2]) -> bool {
+    !webgl::is_webgl1(gl) || (width.is_power_of_two() && height.is_power_of_two())
+}
+
+/// Generates mipmaps of the bound texture of `size` if possible and unbinds it.
+/// Filters are set by the sampler of each channel.
+fn finish_texture(gl: &GL, target: u32, size: [u32; 2]) {
+    if power_of_two(gl, size) {
+        gl.generate_mipmap(target);
+    }
+    gl.bind_texture(target, None);
+}
+
+/// Sets the pixels skipped by the following uploads from a part of an image.
+fn set_unpack_region(gl: &GL, row_length: u32, skip: [u32; 2]) {
+    gl.pixel_storei(GL::UNPACK_ROW_LENGTH, row_length as i32);
+    gl.pixel_storei(GL::UNPACK_SKIP_PIXELS, skip[0] as i32);
+    gl.pixel_storei(GL::UNPACK_SKIP_ROWS, skip[1] as i32);
+}
+
+fn set_texture(gl: &GL, texture_url: &'static str, vflip: bool) -> Option<TextureInfo> {
+    let info = TextureInfo::new(gl, GL::TEXTURE_2D)?;
+    let (gl, cloned_info) = (gl.clone(), info.clone());
+    load_image(texture_url, move |image| {
+        gl.bind_texture(GL::TEXTURE_2D, Some(&cloned_info.texture));
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, vflip as i32);
+        gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            image,
+        )
+        .unwrap_or_else(|e| panic!("{e:?}"));
+        let size = [image.natural_width(), image.natural_height()];
+        finish_texture(&gl, GL::TEXTURE_2D, size);
+        cloned_info.set_resolution([size[0], size[1], 1]);
+    });
+    Some(info)
+}
+
+/// `set_texture` in the worker, which has no image elements.
+fn set_texture_bitmap(gl: &GL, texture_url: &'static str, vflip: bool) -> Option<TextureInfo> {
+    let info = TextureInfo::new(gl, GL::TEXTURE_2D)?;
+    let (gl, cloned_info) = (gl.clone(), info.clone());
+    wasm_bindgen_futures::spawn_local(async move {
+        let image = match load_bitmap(texture_url, vflip).await {
+            Ok(image) => image,
+            Err(e) => {
+                gloo::console::error!(format!("failed to load {texture_url}: {e:?}"));
+                return;
+            }
+        };
+        gl.bind_texture(GL::TEXTURE_2D, Some(&cloned_info.texture));
+        gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            &image,
+        )
+        .unwrap_or_else(|e| panic!("{e:?}"));
+        finish_texture(&gl, GL::TEXTURE_2D, [image.width(), image.height()]);
+        cloned_info.set_resolution([image.width(), image.height(), 1]);
+    });
+    Some(info)
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// the global `createImageBitmap` of the window or the worker
+    #[wasm_bindgen(js_name = createImageBitmap, catch)]
+    fn create_image_bitmap(
+        image: &Blob,
+        options: &js_sys::Object,
+    ) -> Result<js_sys::Promise, JsValue>;
+}
+
+/// Decodes the image, flipped by the options since `UNPACK_FLIP_Y_WEBGL` does not apply to bitmaps.
+async fn load_bitmap(url: &str, vflip: bool) -> Result<ImageBitmap, JsValue> {
+    let response: Response = JsFuture::from(fetch(url)).await?.dyn_into()?;
+    let blob: Blob = JsFuture::from(response.blob()?).await?.dyn_into()?;
+    let options = js_sys::Object::new();
+    if vflip {
+        js_sys::Reflect::set(&options, &"imageOrientation".into(), &"flipY".into())?;
+    }
+    let image = JsFuture::from(create_image_bitmap(&blob, &options)?).await?;
+    image.dyn_into()
+}
+
+fn set_cube_faces(gl: &GL, urls: [&'static str; 6], vflip: bool) -> Option<TextureInfo> {
+    let info = TextureInfo::new(gl, GL::TEXTURE_CUBE_MAP)?;
+    // mipmaps need all the faces
+    let loaded = Arc::new(AtomicU32::new(0));
+    urls.iter().enumerate().for_each(|(face, url)| {
+        let (gl, cloned_info, loaded) = (gl.clone(), info.clone(), Arc::clone(&loaded));
+        load_image(url, move |image| {
+            gl.bind_texture(GL::TEXTURE_CUBE_MAP, Some(&cloned_info.texture));
+            gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, vflip as i32);
+            gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
+                GL::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                0,
+                GL::RGBA as i32,
+                GL::RGBA,
+                GL::UNSIGNED_BYTE,
+                image,
+            )
+            .unwrap_or_else(|e| panic!("{e:?}"));
+            match loaded.fetch_add(1, Ordering::SeqCst) + 1 == 6 {
+                true => {
+                    let (width, height) = (image.natural_width(), image.natural_height());
+                    finish_texture(&gl, GL::TEXTURE_CUBE_MAP, [width, height]);
+                    cloned_info.set_resolution([width, height, 1]);
+                }
+                false => gl.bind_texture(GL::TEXTURE_CUBE_MAP, None),
+            }
+        });
+    });
+    Some(info)
+}
+
+/// positions of +X, -X, +Y, -Y, +Z and -Z in a horizontal cross in faces
+const CUBE_CROSS_FACES: [[u32; 2]; 6] = [[2, 1], [0, 1], [1, 0], [1, 2], [1, 1], [3, 1]];
+
+fn set_cube_cross(gl: &GL, url: &'static str) -> Option<TextureInfo> {
+    let info = TextureInfo::new(gl, GL::TEXTURE_CUBE_MAP)?;
+    let (gl, cloned_info) = (gl.clone(), info.clone());
+    load_image(url, move |image| {
+        let width = image.natural_width();
+        let size = width / 4;
+        gl.bind_texture(GL::TEXTURE_CUBE_MAP, Some(&cloned_info.texture));
+        // cube maps are not flipped, and the flip would move the faces in the cross
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        for (face, [x, y]) in CUBE_CROSS_FACES.iter().enumerate() {
+            set_unpack_region(&gl, width, [x * size, y * size]);
+            gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_html_image_element(
+                GL::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                0,
+                GL::RGBA as i32,
+                size as i32,
+                size as i32,
+                0,
+                GL::RGBA,
+                GL::UNSIGNED_BYTE,
+                image,
+            )
+            .unwrap_or_else(|e| panic!("{e:?}"));
+        }
+        set_unpack_region(&gl, 0, [0, 0]);
+        finish_texture(&gl, GL::TEXTURE_CUBE_MAP, [size, size]);
+        cloned_info.set_resolution([size, size, 1]);
+    });
+    Some(info)
+}
+
+fn set_volume_tiles(gl: &GL, url: &'static str, tiles: [u32; 2]) -> Option<TextureInfo> {
+    let info = TextureInfo::new(gl, GL::TEXTURE_3D)?;
+    let (gl, cloned_info) = (gl.clone(), info.clone());
+    load_image(url, move |image| {
+        let [columns, rows] = tiles;
+        let (width, height) = (
+            image.natural_width() / columns,
+            image.natural_height() / rows,
+        );
+        let depth = columns * rows;
+        gl.bind_texture(GL::TEXTURE_3D, Some(&cloned_info.texture));
+        // 3D textures cannot be flipped
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.tex_image_3d_with_opt_u8_array(
+            GL::TEXTURE_3D,
+            0,
+            GL::RGBA8 as i32,
+            width as i32,
+            height as i32,
+            depth as i32,
+            0,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            None,
+        )
+        .unwrap_or_else(|e| panic!("{e:?}"));
+        (0..depth).for_each(|z| {
+            let skip = [z % columns * width, z / columns * height];
+            set_unpack_region(&gl, image.natural_width(), skip);
+            gl.tex_sub_image_3d_with_html_image_element(
+                GL::TEXTURE_3D,
+                0,
+                0,
+                0,
+                z as i32,
+                width as i32,
+                height as i32,
+                1,
+                GL::RGBA,
+                GL::UNSIGNED_BYTE,
+                image,
+            )
+            .unwrap_or_else(|e| panic!("{e:?}"));
+        });
+        set_unpack_region(&gl, 0, [0, 0]);
+        finish_texture(&gl, GL::TEXTURE_3D, [width, height]);
+        cloned_info.set_resolution([width, height, depth]);
+    });
+    Some(info)
+}
+
+fn set_volume_binary(gl: &GL, url: &'static str) -> Option<TextureInfo> {
+    let info = TextureInfo::new(gl, GL::TEXTURE_3D)?;
+    let (gl, cloned_info) = (gl.clone(), info.clone());
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = match fetch_bytes(url).await {
+            Ok(bytes) => upload_volume_binary(&gl, &cloned_info, &bytes),
+            Err(e) => Err(format!("{e:?}")),
+        };
+        if let Err(e) = result {
+            gloo::console::error!(format!("failed to load volume {url}: {e}"));
+        }
+    });
+    Some(info)
+}
+
+/// `fetch` of the window or the worker
+fn fetch(url: &str) -> js_sys::Promise {
+    match js_sys::global().dyn_into::<WorkerGlobalScope>() {
+        Ok(scope) => scope.fetch_with_str(&worker::resolve_url(url)),
+        Err(_) => gloo::utils::window().fetch_with_str(url),
+    }
+}
+
+pub async fn fetch_bytes(url: &str) -> Result<Vec<u8>, JsValue> {
+    let response = JsFuture::from(fetch(url)).await?;
+    let response: Response = response.dyn_into()?;
+    let buffer = JsFuture::from(response.array_buffer()?).await?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+/// `BIN\0` in little endian
+const VOLUME_SIGNATURE: u32 = 0x004e4942;
+
+/// Uploads a volume of Shadertoy's binary format, a 20 bytes header of
+/// the signature, the size of x, y and z as u32, the number of channels as u8,
+/// the layout as u8 and the format as u16, followed by the texels.
+/// Only the 8 bits format is supported.
+fn upload_volume_binary(gl: &GL, info: &TextureInfo, bytes: &[u8]) -> Result<(), String> {
+    let header = |offset: usize| match bytes.get(offset..offset + 4) {
+        Some(word) => Ok(u32::from_le_bytes(word.try_into().unwrap())),
+        None => Err(String::from("too short header")),
+    };
+    if header(0)? != VOLUME_SIGNATURE {
+        return Err(String::from("invalid signature"));
+    }
+    let size = [header(4)?, header(8)?, header(12)?];
+    let format = header(16)?;
+    let (channels, format) = (format & 0xff, format >> 16);
+    let (internal_format, pixel_format) = match (channels, format) {
+        (1, 0) => (GL::R8, GL::RED),
+        (2, 0) => (GL::RG8, GL::RG),
+        (3, 0) => (GL::RGB8, GL::RGB),
+        (4, 0) => (GL::RGBA8, GL::RGBA),
+        _ => {
+            return Err(format!(
+                "unsupported format: {channels} channels of {format}"
+            ))
+        }
+    };
+    let texels = &bytes[20..];
+    let length = size.iter().product::<u32>() as usize * channels as usize;
+    if texels.len() < length {
+        return Err(String::from("too short data"));
+    }
+    gl.bind_texture(GL::TEXTURE_3D, Some(&info.texture));
+    // 3D uploads from arrays fail with the flip
+    gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+    gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
+    gl.tex_image_3d_with_opt_u8_array(
+        GL::TEXTURE_3D,
+        0,
+        internal_format as i32,
+        size[0] as i32,
+        size[1] as i32,
+        size[2] as i32,
+        0,
+        pixel_format,
+        GL::UNSIGNED_BYTE,
+        Some(&texels[..length]),
+    )
+    .map_err(|e| format!("{e:?}"))?;
+    gl.pixel_storei(GL::UNPACK_ALIGNMENT, 4);
+    finish_texture(gl, GL::TEXTURE_3D, [size[0], size[1]]);
+    info.set_resolution(size);
+    Ok(())
+}
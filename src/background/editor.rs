@@ -0,0 +1,262 @@
+use super::channel::{ChannelInput, ChannelSource, Filter, Wrap};
+use super::*;
+use gloo::timers::callback::Timeout;
+
+/// milliseconds from the last input to the compile
+const COMPILE_DELAY: u32 = 300;
+
+/// the end of the time slider, `iTime` starts from the minutes and seconds of the clock
+const MAX_TIME: f64 = 3600.0;
+
+/// a frame at 60 fps, the advance of the step button and the arrow keys
+pub const STEP_TIME: f64 = 1.0 / 60.0;
+
+/// Controls of `iTime` from the editor and the keyboard shortcuts.
+#[derive(Clone, Copy, Debug)]
+pub enum Clock {
+    Play,
+    Pause,
+    /// pauses and advances by the seconds, backwards if negative
+    Step(f64),
+    Seek(f64),
+    /// the seconds of `iTime` in a second
+    Speed(f64),
+}
+
+/// A compile error of an edited pass, `lines` are the line numbers in the editor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditError {
+    pub message: String,
+    pub lines: Vec<u32>,
+}
+
+/// Edits the passes of the standalone background, compiled while typing.
+#[derive(Debug)]
+pub struct Editor {
+    pass: usize,
+    /// the edited sources of all passes, starting from the built ones
+    texts: Vec<String>,
+    /// the debounced compile of the current pass
+    compile: Option<Timeout>,
+}
+
+pub enum Msg {
+    Input(String),
+    Compile,
+    SelectPass(usize),
+    Export,
+}
+
+#[derive(Clone, Debug, PartialEq, Properties)]
+pub struct Props {
+    pub shader_name: String,
+    /// `iTime` of the latest frame
+    pub time: f64,
+    pub paused: bool,
+    /// the errors by the indices of the passes
+    pub errors: BTreeMap<usize, EditError>,
+    /// the index of the pass and its source
+    pub on_edit: Callback<(usize, String)>,
+    pub on_clock: Callback<Clock>,
+}
+
+impl Component for Editor {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let texts = get_shader(&ctx.props().shader_name).map_or_else(Vec::new, |shader| {
+            let passes = shader.passes.iter();
+            passes.map(|pass| pass.source.to_string()).collect()
+        });
+        Self {
+            // the image pass
+            pass: texts.len().saturating_sub(1),
+            texts,
+            compile: None,
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let (props, link) = (ctx.props(), ctx.link());
+        let (shader, text) = match (get_shader(&props.shader_name), self.texts.get(self.pass)) {
+            (Some(shader), Some(text)) => (shader, text),
+            _ => return html! {},
+        };
+        let error = props.errors.get(&self.pass);
+        let line_count = text.lines().count().max(1);
+        let columns = text.lines().map(|line| line.chars().count()).max();
+        let gutter = (1..=line_count as u32).map(|line| {
+            let class = error.and_then(|error| error.lines.contains(&line).then_some("error"));
+            html! { <div class={ classes!(class) }>{ line }</div> }
+        });
+        let options = shader.passes.iter().enumerate().map(|(idx, pass)| {
+            let mark = match props.errors.contains_key(&idx) {
+                true => " ⚠",
+                false => "",
+            };
+            html! { <option selected={ idx == self.pass }>{ file_name(pass.path) }{ mark }</option> }
+        });
+        let (play_label, play_clock) = match props.paused {
+            true => ("▶", Clock::Play),
+            false => ("❚❚", Clock::Pause),
+        };
+        html! {
+            <div class="editor">
+                <div class="editor_controls">
+                    if shader.passes.len() > 1 {
+                        <select onchange={ link.callback(|e: Event| {
+                            let select = e.target_unchecked_into::<HtmlSelectElement>();
+                            Msg::SelectPass(select.selected_index().max(0) as usize)
+                        }) }>{ for options }</select>
+                    }
+                    <button onclick={ props.on_clock.reform(move |_| play_clock) }>{ play_label }</button>
+                    <button onclick={ props.on_clock.reform(|_| Clock::Step(STEP_TIME)) }>{ "step" }</button>
+                    <input type="range" min="0" max={ MAX_TIME.to_string() } step="0.01"
+                        value={ props.time.to_string() }
+                        oninput={ props.on_clock.reform(|e: InputEvent| {
+                            Clock::Seek(e.target_unchecked_into::<HtmlInputElement>().value_as_number())
+                        }) } />
+                    <span class="editor_time">{ format!("{:.2}s", props.time) }</span>
+                    <button onclick={ link.callback(|_| Msg::Export) }>{ "export" }</button>
+                </div>
+                // the gutter and the text scroll together since the text area fits its content
+                <div class="editor_code">
+                    <div class="editor_gutter">{ for gutter }</div>
+                    <textarea value={ text.clone() } spellcheck="false" wrap="off"
+                        rows={ line_count.to_string() } cols={ columns.unwrap_or(0).max(1).to_string() }
+                        oninput={ link.callback(|e: InputEvent| {
+                            Msg::Input(e.target_unchecked_into::<HtmlTextAreaElement>().value())
+                        }) } />
+                </div>
+                if let Some(error) = error { <pre class="editor_error">{ error.message.clone() }</pre> }
+            </div>
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Msg) -> bool {
+        match msg {
+            Msg::Input(text) => {
+                self.texts[self.pass] = text;
+                let link = ctx.link().clone();
+                let timeout = Timeout::new(COMPILE_DELAY, move || link.send_message(Msg::Compile));
+                self.compile = Some(timeout);
+                true
+            }
+            Msg::Compile => {
+                self.compile = None;
+                let text = self.texts[self.pass].clone();
+                ctx.props().on_edit.emit((self.pass, text));
+                false
+            }
+            Msg::SelectPass(idx) if idx < self.texts.len() => {
+                // the pending input belongs to the previous pass
+                if self.compile.take().is_some() {
+                    let text = self.texts[self.pass].clone();
+                    ctx.props().on_edit.emit((self.pass, text));
+                }
+                self.pass = idx;
+                true
+            }
+            Msg::SelectPass(_) => false,
+            Msg::Export => {
+                if let Some(shader) = get_shader(&ctx.props().shader_name) {
+                    let path = shader.passes[self.pass].path;
+                    let header = channel_directives(shader, self.pass);
+                    download(file_name(path), &(header + &self.texts[self.pass]));
+                }
+                false
+            }
+        }
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// The `#iChannelN` directives of a pass, which are removed from the built source.
+/// Textures refer to `resources/` from the `.frag` file, and the selfie keeps the hashed name of the build.
+fn channel_directives(shader: RenderGraphSource, pass: usize) -> String {
+    let path = shader.passes[pass].path;
+    let resources = "../".repeat(path.matches('/').count() + 1) + "resources/";
+    let resource = |url: &str| resources.clone() + url.trim_start_matches("./");
+    let channels = shader.passes[pass].channels.iter().enumerate();
+    channels
+        .filter_map(|(idx, channel)| Some((idx, (*channel)?)))
+        .map(|(idx, ChannelSource { input, sampler })| {
+            let (input, options) = match input {
+                ChannelInput::Texture(url) => (resource(url), Vec::new()),
+                ChannelInput::CubeFaces(urls) => {
+                    // `{}` is where the first two faces differ by `px` and `nx`
+                    let pattern = urls[0]
+                        .match_indices("px")
+                        .find(|(i, _)| urls[1].get(*i..*i + 2) == Some("nx"))
+                        .map(|(i, _)| format!("{}{{}}{}", &urls[0][..i], &urls[0][i + 2..]));
+                    let url = pattern.unwrap_or_else(|| urls[0].to_string());
+                    (resource(&url), vec![("Type", String::from("cube"))])
+                }
+                ChannelInput::CubeCross(url) => {
+                    (resource(url), vec![("Type", String::from("cube"))])
+                }
+                ChannelInput::VolumeBinary(url) => {
+                    (resource(url), vec![("Type", String::from("3d"))])
+                }
+                ChannelInput::VolumeTiles(url, [columns, rows]) => (
+                    resource(url),
+                    vec![
+                        ("Type", String::from("3d")),
+                        ("Tiles", format!("{columns}x{rows}")),
+                    ],
+                ),
+                ChannelInput::Buffer(buffer) if buffer == pass => {
+                    (String::from("self"), Vec::new())
+                }
+                ChannelInput::Buffer(buffer) => {
+                    let name = file_name(shader.passes[buffer].path);
+                    (format!("file://{name}"), Vec::new())
+                }
+                ChannelInput::Keyboard => (String::from("keyboard"), Vec::new()),
+                ChannelInput::Video(url) => (resource(url), Vec::new()),
+                ChannelInput::Webcam => (String::from("webcam"), Vec::new()),
+                ChannelInput::Audio(url) => (format!("audio:{}", resource(url)), Vec::new()),
+            };
+            let filter = match sampler.filter {
+                Filter::Nearest => "nearest",
+                Filter::Linear => "linear",
+                Filter::Mipmap => "mipmap",
+            };
+            let wrap = match sampler.wrap {
+                Wrap::Clamp => "clamp",
+                Wrap::Repeat => "repeat",
+                Wrap::Mirror => "mirror",
+            };
+            let options = options.into_iter().chain([
+                ("Filter", filter.to_string()),
+                ("Wrap", wrap.to_string()),
+                ("VFlip", sampler.vflip.to_string()),
+            ]);
+            let options =
+                options.map(|(key, value)| format!("#iChannel{idx}::{key} \"{value}\"\n"));
+            format!("#iChannel{idx} \"{input}\"\n") + &options.collect::<String>()
+        })
+        .collect()
+}
+
+/// Saves `text` as a file through a temporary link.
+fn download(file_name: &str, text: &str) {
+    let parts = js_sys::Array::of1(&text.into());
+    let blob =
+        Blob::new_with_str_sequence_and_options(&parts, BlobPropertyBag::new().type_("text/plain"))
+            .expect_throw("failed to create blob");
+    let url = Url::create_object_url_with_blob(&blob).expect_throw("failed to create object url");
+    let anchor: HtmlAnchorElement = gloo::utils::document()
+        .create_element("a")
+        .expect_throw("failed to create anchor")
+        .dyn_into()
+        .expect_throw("failed to cast anchor");
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+    let _ = Url::revoke_object_url(&url);
+}
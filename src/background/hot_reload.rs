@@ -0,0 +1,80 @@
+use super::*;
+
+/// the WebSocket of `src/bin/watch.rs`
+const WATCHER_URL: &str = "ws://127.0.0.1:8081";
+
+/// A pass pushed by the watcher, preprocessed and validated like the built ones.
+#[derive(Clone, Debug)]
+pub enum HotShader {
+    Source {
+        path: String,
+        source: String,
+        source_lines: Vec<u32>,
+    },
+    Error {
+        path: String,
+        message: String,
+    },
+}
+
+impl HotShader {
+    pub fn path(&self) -> &str {
+        match self {
+            HotShader::Source { path, .. } | HotShader::Error { path, .. } => path,
+        }
+    }
+}
+
+/// Receives the shaders saved while the page is open, for debug builds.
+/// Without the watcher, the socket fails once and stays closed.
+#[derive(Debug)]
+pub struct HotReload {
+    socket: WebSocket,
+    _handlers: Vec<EventListener>,
+}
+
+impl HotReload {
+    pub fn connect(on_shader: Callback<HotShader>) -> Option<HotReload> {
+        let socket = WebSocket::new(WATCHER_URL).ok()?;
+        let on_message = move |e: &Event| {
+            let e = MessageEvent::from(wasm_bindgen::JsValue::from(e.clone()));
+            match e.data().as_string().as_deref().and_then(parse_message) {
+                Some(shader) => on_shader.emit(shader),
+                None => gloo::console::error!("invalid message from the shader watcher"),
+            }
+        };
+        Some(HotReload {
+            _handlers: vec![EventListener::new(&socket, "message", on_message)],
+            socket,
+        })
+    }
+}
+
+impl Drop for HotReload {
+    fn drop(&mut self) {
+        let _ = self.socket.close();
+    }
+}
+
+/// `source {path}\n{line numbers}\n{source}` or `error {path}\n{messages}`
+fn parse_message(message: &str) -> Option<HotShader> {
+    let (header, body) = message.split_once('\n')?;
+    if let Some(path) = header.strip_prefix("error ") {
+        return Some(HotShader::Error {
+            path: path.to_string(),
+            message: body.to_string(),
+        });
+    }
+    let path = header.strip_prefix("source ")?;
+    let (lines, source) = body.split_once('\n')?;
+    let source_lines = lines
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<u32>, _>>()
+        .ok()?;
+    Some(HotShader::Source {
+        path: path.to_string(),
+        source: source.to_string(),
+        source_lines,
+    })
+}
@@ -0,0 +1,91 @@
+use super::channel;
+use super::*;
+use gloo::events::EventListener;
+use std::sync::Mutex;
+use WebGl2RenderingContext as GL;
+
+/// the number of key codes, the width of the texture
+const KEY_COUNT: usize = 256;
+
+/// Key states of `#iChannelN "keyboard"` in the layout of Shadertoy, a 256x3 texture indexed by `keyCode`:
+/// the first row is 1 while the key is down, the second row is 1 only on the frame the key is pressed
+/// and the third row toggles on every press.
+#[derive(Debug)]
+pub struct Keyboard {
+    texture: WebGlTexture,
+    state: Arc<Mutex<[[u8; KEY_COUNT]; 3]>>,
+    _handlers: Vec<EventListener>,
+}
+
+impl Keyboard {
+    /// Listens to the keys of the window.
+    pub fn new(gl: &GL) -> Option<Keyboard> {
+        let texture = gl.create_texture()?;
+        let state = Arc::new(Mutex::new([[0; KEY_COUNT]; 3]));
+        let key_code = |e: &Event| {
+            let e = KeyboardEvent::from(wasm_bindgen::JsValue::from(e.clone()));
+            (e.key_code() as usize).min(KEY_COUNT - 1)
+        };
+        let cloned_state = Arc::clone(&state);
+        let on_down = move |e: &Event| {
+            let key = key_code(e);
+            let mut state = cloned_state.lock().unwrap();
+            // key repeats are not presses
+            if state[0][key] == 0 {
+                state[1][key] = 255;
+                state[2][key] = 255 - state[2][key];
+            }
+            state[0][key] = 255;
+        };
+        let cloned_state = Arc::clone(&state);
+        let on_up = move |e: &Event| cloned_state.lock().unwrap()[0][key_code(e)] = 0;
+        // keyup never comes after the focus is lost
+        let cloned_state = Arc::clone(&state);
+        let on_blur = move |_: &Event| cloned_state.lock().unwrap()[0] = [0; KEY_COUNT];
+        let win = gloo::utils::window();
+        let _handlers = vec![
+            EventListener::new(&win, "keydown", on_down),
+            EventListener::new(&win, "keyup", on_up),
+            EventListener::new(&win, "blur", on_blur),
+        ];
+        Some(Keyboard {
+            texture,
+            state,
+            _handlers,
+        })
+    }
+
+    pub fn texture(&self) -> &WebGlTexture {
+        &self.texture
+    }
+
+    pub fn resolution(&self) -> [f32; 3] {
+        [KEY_COUNT as f32, 3.0, 1.0]
+    }
+
+    /// Uploads the key states for this frame and clears the presses.
+    pub fn upload(&self, gl: &GL) {
+        let texels = {
+            let mut state = self.state.lock().unwrap();
+            let texels = state.concat();
+            state[1] = [0; KEY_COUNT];
+            texels
+        };
+        let (internal_format, format) = channel::single_channel_format(gl);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.texture));
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 0);
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            internal_format,
+            KEY_COUNT as i32,
+            3,
+            0,
+            format,
+            GL::UNSIGNED_BYTE,
+            Some(&texels),
+        )
+        .unwrap_or_else(|e| panic!("{e:?}"));
+        gl.bind_texture(GL::TEXTURE_2D, None);
+    }
+}
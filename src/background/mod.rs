@@ -1,67 +1,299 @@
 use crate::*;
 use gloo::events::EventListener;
-use js_sys::Date;
+use js_sys::{Date, Math};
+use std::collections::BTreeMap;
 use std::sync::{
-    atomic::{AtomicI32, AtomicU32, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering},
+    Arc, Mutex,
 };
 use wasm_bindgen::JsCast;
 
 mod shaders {
-    use super::ShaderSource;
+    use super::channel::{ChannelInput, ChannelSource, Filter, SamplerSettings, Wrap};
+    use super::webgl::{RenderGraphSource, ShaderSource};
     include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
 }
+mod audio;
+mod channel;
+mod editor;
+mod hot_reload;
+mod keyboard;
+mod render_graph;
+mod renderer;
+mod resolution;
+mod video;
+mod visibility;
 mod webgl;
-use webgl::{Pipeline, ShaderSource};
+mod worker;
+use editor::{Clock, EditError};
+use hot_reload::{HotReload, HotShader};
+use render_graph::RenderGraph;
+use renderer::{Pointer, Renderer};
+use visibility::VisibilityListener;
+use webgl::{RenderGraphSource, ShaderError};
+pub use worker::{in_worker, run_worker};
+use worker::{FrameMessage, WorkerRenderer};
 
 #[derive(Debug)]
 pub struct BackGround {
+    /// the shader of the render graph, replaced when `Props::shader_name` changes
+    shader_name: String,
     gl: Option<WebGl2RenderingContext>,
     canvas: NodeRef,
-    pipeline: Option<Pipeline>,
+    fallback_canvas: NodeRef,
+    error: Option<String>,
+    /// `None` until the first render and while the context is lost
+    renderer: Option<Renderer>,
+    /// renders instead of `renderer` with `Props::worker`
+    worker: Option<WorkerRenderer>,
     render_loop: Option<gloo::render::AnimationFrame>,
-    frame_count: u32,
     init_time: f64,
+    /// the seconds of `iTime` in a second
+    speed: f64,
+    /// `iSeed`
+    seed: u32,
+    /// total milliseconds of the pauses, subtracted from the timestamps to freeze `iTime`
+    paused_time: f64,
+    /// `performance.now()` when the loop stopped
+    pause_started: Option<f64>,
+    /// `iTime` paused by `?paused=1`, the editor or the shortcuts
+    stopped_time: Option<f64>,
+    /// renders a frame while `stopped_time` is set, e.g. the first one or after a step, a seek or an edit
+    redraw: bool,
     mouse_listener: MouseListner,
+    visibility: Option<VisibilityListener>,
+    /// set between `webglcontextlost` and `webglcontextrestored`
+    context_lost: bool,
+    _context_listeners: Vec<EventListener>,
+    /// the state of `AudioSwitch`, applied again to the restored render graph
+    audio_playing: bool,
+    /// only in the standalone background
+    _shortcut_listener: Option<EventListener>,
+    /// connected to `src/bin/watch.rs` in debug builds
+    _hot_reload: Option<HotReload>,
+    /// the errors of the hot reloaded passes by their indices, shown over the running shader
+    shader_errors: BTreeMap<usize, String>,
+    /// the errors of the passes edited by `Editor`
+    edit_errors: BTreeMap<usize, EditError>,
 }
 
 pub enum Msg {
     Render(f64),
+    /// the message over the poster, until the next shader
+    Error(String),
+    /// the worker did not start, e.g. without module workers, and whether the canvas was transferred to it
+    WorkerFailed(bool),
+    /// whether the background is visible and may be animated
+    SetActive(bool),
+    ContextLost,
+    ContextRestored,
+    HotShader(HotShader),
+    /// the index of the pass and its source from `Editor`
+    Edit(usize, String),
+    Clock(Clock),
+    /// `KeyboardEvent.key` of a shortcut
+    Shortcut(String),
 }
 
 #[derive(Clone, Debug, PartialEq, Properties)]
 pub struct Props {
+    /// the shader of the document, cross-faded when it changes
     pub shader_name: String,
+    /// `?doc=none`, the page is only the background and the keyboard shortcuts control `iTime`
+    #[prop_or_default]
+    pub standalone: bool,
+    /// the state of `AudioSwitch`
+    #[prop_or_default]
+    pub audio_playing: bool,
+    /// shows `Editor` over the right half
+    #[prop_or_default]
+    pub edit: bool,
+    /// `iTime` of the first frame, the minutes and seconds of the clock by default
+    #[prop_or_default]
+    pub time: Option<f64>,
+    /// the seconds of `iTime` in a second, 1 by default
+    #[prop_or_default]
+    pub speed: Option<f64>,
+    /// starts with the first frame paused
+    #[prop_or_default]
+    pub paused: bool,
+    /// `iSeed`, random by default
+    #[prop_or_default]
+    pub seed: Option<u32>,
+    /// the seconds of the cross-fade to the shader of the next document, `FADE_DURATION` by default
+    #[prop_or_default]
+    pub fade: Option<f64>,
+    /// renders in a worker through `OffscreenCanvas` where it is supported, except for the standalone background
+    #[prop_or_default]
+    pub worker: bool,
+}
+
+impl Props {
+    fn fade_duration(&self) -> f64 {
+        self.fade.unwrap_or(FADE_DURATION)
+    }
+}
+
+/// the seconds of the cross-fade between the shaders by default
+const FADE_DURATION: f64 = 1.0;
+
+/// the range of the random `iSeed`, whose integers are exact as floats
+const SEED_RANGE: f64 = 65536.0;
+
+/// the parameters of `?t=12.5&speed=0.5&paused=1&seed=42`, written to the address to share a paused frame
+pub const TIME_QUERY_KEYS: [&str; 4] = ["t", "speed", "paused", "seed"];
+
+/// the length of the `iTouch` uniform array
+pub const TOUCH_COUNT: usize = 4;
+
+/// A pressed pointer in the coordinates of `gl_FragCoord`.
+#[derive(Clone, Copy, Debug)]
+struct TouchPoint {
+    pointer_id: i32,
+    position: [i32; 2],
+    start_position: [i32; 2],
+}
+
+/// Pointer state in the coordinates of `gl_FragCoord`.
+/// The primary pointer drives `iMouse`, and every pressed pointer is a touch point.
+#[derive(Debug, Default)]
+struct MouseState {
+    position: [AtomicI32; 2],
+    click_position: [AtomicI32; 2],
+    pressed: AtomicBool,
+    /// set by `pointerdown`, consumed by the next rendered frame
+    clicked: AtomicBool,
+    touches: Mutex<[Option<TouchPoint>; TOUCH_COUNT]>,
 }
 
 #[derive(Debug)]
 pub struct MouseListner {
-    mouse_position: Arc<[AtomicI32; 2]>,
+    state: Arc<MouseState>,
     _handlers: Vec<EventListener>,
 }
 
 impl MouseListner {
+    /// Listens to the pointer events of mouse, touch and pen on the window.
     fn set() -> MouseListner {
-        let mouse_position: Arc<[AtomicI32; 2]> = Default::default();
-        let cloned_mp = Arc::clone(&mouse_position);
-        let closure = move |e: &Event| {
-            let e = MouseEvent::from(wasm_bindgen::JsValue::from(e.clone()));
-            cloned_mp[0].store(e.client_x(), Ordering::SeqCst);
-            cloned_mp[1].store(e.client_y(), Ordering::SeqCst);
+        let state: Arc<MouseState> = Default::default();
+        let pointer = |e: &Event| {
+            let e = PointerEvent::from(wasm_bindgen::JsValue::from(e.clone()));
+            let height = gloo::utils::document_element().client_height();
+            (
+                e.pointer_id(),
+                e.is_primary(),
+                [e.client_x(), height - e.client_y()],
+            )
         };
-        let win = &gloo::utils::window();
-        let mut _handlers = vec![EventListener::new(win, "mousemove", closure.clone())];
-        if let Ok(Some(parent)) = gloo::utils::window().parent() {
-            _handlers.push(EventListener::new(&parent, "mousemove", closure));
-        }
-        MouseListner {
-            mouse_position,
-            _handlers,
-        }
+        let cloned_state = Arc::clone(&state);
+        let on_move = move |e: &Event| {
+            let (pointer_id, primary, [x, y]) = pointer(e);
+            if primary {
+                cloned_state.position[0].store(x, Ordering::SeqCst);
+                cloned_state.position[1].store(y, Ordering::SeqCst);
+            }
+            let mut touches = cloned_state.touches.lock().unwrap();
+            if let Some(touch) = touches
+                .iter_mut()
+                .flatten()
+                .find(|touch| touch.pointer_id == pointer_id)
+            {
+                touch.position = [x, y];
+            }
+        };
+        let cloned_state = Arc::clone(&state);
+        let on_down = move |e: &Event| {
+            let (pointer_id, primary, [x, y]) = pointer(e);
+            if primary {
+                cloned_state.position[0].store(x, Ordering::SeqCst);
+                cloned_state.position[1].store(y, Ordering::SeqCst);
+                cloned_state.click_position[0].store(x, Ordering::SeqCst);
+                cloned_state.click_position[1].store(y, Ordering::SeqCst);
+                cloned_state.pressed.store(true, Ordering::SeqCst);
+                cloned_state.clicked.store(true, Ordering::SeqCst);
+            }
+            // touches beyond `TOUCH_COUNT` are ignored
+            let mut touches = cloned_state.touches.lock().unwrap();
+            if let Some(slot) = touches.iter_mut().find(|touch| touch.is_none()) {
+                *slot = Some(TouchPoint {
+                    pointer_id,
+                    position: [x, y],
+                    start_position: [x, y],
+                });
+            }
+        };
+        let cloned_state = Arc::clone(&state);
+        let on_up = move |e: &Event| {
+            let (pointer_id, primary, _) = pointer(e);
+            if primary {
+                cloned_state.pressed.store(false, Ordering::SeqCst);
+            }
+            let mut touches = cloned_state.touches.lock().unwrap();
+            touches
+                .iter_mut()
+                .filter(|touch| matches!(touch, Some(touch) if touch.pointer_id == pointer_id))
+                .for_each(|touch| *touch = None);
+        };
+        let win = gloo::utils::window();
+        let _handlers = vec![
+            EventListener::new(&win, "pointermove", on_move),
+            EventListener::new(&win, "pointerdown", on_down),
+            EventListener::new(&win, "pointerup", on_up.clone()),
+            // a touch becomes a scroll of the page
+            EventListener::new(&win, "pointercancel", on_up),
+        ];
+        MouseListner { state, _handlers }
     }
 }
 
-fn get_shader(shader_name: &str) -> Option<ShaderSource> {
+impl Pointer for MouseListner {
+    /// `iMouse`: the sign of z is the button state and w is positive only on the frame of the click.
+    /// Unlike Shadertoy, xy follows the cursor without pressing.
+    fn uniform(&self) -> [f32; 4] {
+        let MouseState {
+            position,
+            click_position,
+            pressed,
+            clicked,
+            ..
+        } = &*self.state;
+        let sign = |positive: bool| if positive { 1.0 } else { -1.0 };
+        [
+            position[0].load(Ordering::SeqCst) as f32,
+            position[1].load(Ordering::SeqCst) as f32,
+            click_position[0].load(Ordering::SeqCst) as f32 * sign(pressed.load(Ordering::SeqCst)),
+            click_position[1].load(Ordering::SeqCst) as f32
+                * sign(clicked.swap(false, Ordering::SeqCst)),
+        ]
+    }
+
+    /// `iTouch` and `iTouchCount`: xy is the current position and zw is the start position
+    /// of each pressed pointer, packed at the front
+    fn touch_uniform(&self) -> ([[f32; 4]; TOUCH_COUNT], i32) {
+        let touches = self.state.touches.lock().unwrap();
+        let mut res = [[0.0; 4]; TOUCH_COUNT];
+        let mut count = 0;
+        touches.iter().flatten().for_each(|touch| {
+            let ([x, y], [sx, sy]) = (touch.position, touch.start_position);
+            res[count] = [x as f32, y as f32, sx as f32, sy as f32];
+            count += 1;
+        });
+        (res, count as i32)
+    }
+}
+
+/// whether the shader has an audio channel, to show `AudioSwitch`
+pub fn has_audio(shader_name: &str) -> bool {
+    get_shader(shader_name).is_some_and(|shader| {
+        shader
+            .passes
+            .iter()
+            .flat_map(|pass| pass.channels.iter().flatten())
+            .any(|channel| matches!(channel.input, channel::ChannelInput::Audio(_)))
+    })
+}
+
+fn get_shader(shader_name: &str) -> Option<RenderGraphSource> {
     let shaders = shaders::get_texts();
     shaders
         .get(&shader_name)
@@ -73,82 +305,493 @@ impl Component for BackGround {
     type Message = Msg;
     type Properties = Props;
 
-    fn create(_ctx: &Context<Self>) -> Self {
-        let date = Date::new(&Date::now().into());
-        let init_time = (date.get_minutes() * 60 + date.get_seconds()) as f64;
+    fn create(ctx: &Context<Self>) -> Self {
+        let props = ctx.props();
+        let init_time = props.time.unwrap_or_else(|| {
+            let date = Date::new(&Date::now().into());
+            (date.get_minutes() * 60 + date.get_seconds()) as f64
+        });
         Self {
+            shader_name: props.shader_name.clone(),
             gl: None,
             canvas: Default::default(),
-            pipeline: None,
+            fallback_canvas: Default::default(),
+            error: None,
+            renderer: None,
+            worker: None,
             render_loop: None,
-            frame_count: 0,
             init_time,
+            speed: props.speed.unwrap_or(1.0),
+            seed: props
+                .seed
+                .unwrap_or_else(|| (Math::random() * SEED_RANGE) as u32),
+            paused_time: 0.0,
+            pause_started: None,
+            stopped_time: props.paused.then_some(init_time),
+            redraw: true,
             mouse_listener: MouseListner::set(),
+            visibility: None,
+            context_lost: false,
+            _context_listeners: Vec::new(),
+            audio_playing: props.audio_playing,
+            _shortcut_listener: props.standalone.then(|| listen_shortcuts(ctx)),
+            _hot_reload: None,
+            shader_errors: BTreeMap::new(),
+            edit_errors: BTreeMap::new(),
         }
     }
 
-    fn view(&self, _: &Context<Self>) -> Html {
-        html! { <canvas ref={ self.canvas.clone() } class="background"></canvas> }
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let errors = self.shader_errors.values().cloned().collect::<Vec<_>>();
+        let previous_time = self
+            .renderer
+            .as_ref()
+            .and_then(|renderer| renderer.previous_time);
+        // a canvas with a WebGL context cannot be switched to 2D,
+        // so the message covers it and it stays to render the next shader
+        html! {
+            <>
+            <canvas ref={ self.canvas.clone() } class="background"></canvas>
+            if self.error.is_some() {
+                <canvas ref={ self.fallback_canvas.clone() } class="background"></canvas>
+            } else {
+                if !errors.is_empty() { <pre class="shader_error">{ errors.join("\n") }</pre> }
+                if ctx.props().edit {
+                    <editor::Editor key={ self.shader_name.clone() } shader_name={ self.shader_name.clone() }
+                        time={ previous_time.unwrap_or(self.init_time) }
+                        paused={ self.stopped_time.is_some() }
+                        errors={ self.edit_errors.clone() }
+                        on_edit={ ctx.link().callback(|(idx, source)| Msg::Edit(idx, source)) }
+                        on_clock={ ctx.link().callback(Msg::Clock) } />
+                }
+            }
+            </>
+        }
     }
 
-    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
-        let canvas = self.canvas.cast::<HtmlCanvasElement>().unwrap();
-        self.gl = { || canvas.get_context("webgl2").ok()??.dyn_into().ok() }();
-        let shader_name = &ctx.props().shader_name;
-        if let Some(gl) = &self.gl {
-            webgl::init_gl(gl);
-            if let Some(shader) = get_shader(shader_name) {
-                self.pipeline = Some(webgl::create_pipeline(gl, shader));
-            } else {
-                gloo::utils::window()
-                    .alert_with_message("failed to load shader")
-                    .expect_throw("failed to show alert");
+    /// Applies the state of `AudioSwitch` and the shader of the next document.
+    /// The other props only initialize the clock.
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        let props = ctx.props();
+        if props.audio_playing != self.audio_playing {
+            self.audio_playing = props.audio_playing;
+            if let Some(render_graph) = self.render_graph() {
+                render_graph.set_audio_playing(props.audio_playing);
             }
-        } else {
-            let ctx: CanvasRenderingContext2d =
-                { || canvas.get_context("2d").ok()??.dyn_into().ok() }()
-                    .expect_throw("failed to init rendering context 2d");
-            ctx.set_font("30px serif");
-            let y = canvas.height() - 30;
-            ctx.fill_text("Failed to init WebGL2...", 30.0, y as f64)
-                .unwrap_or_else(|e| gloo::console::log!(format!("{e:?}")));
-        }
-        if first_render {
+        }
+        if props.shader_name == self.shader_name {
+            return false;
+        }
+        self.shader_name = props.shader_name.clone();
+        // without WebGL the message stays over the poster of the next shader
+        if self.gl.is_none() && self.worker.is_none() {
+            return true;
+        }
+        self.change_render_graph(ctx);
+        // a frame of the new shader even if the loop is paused for reduced motion
+        if self.render_loop.is_none() && !self.context_lost {
             self.set_render_loop(ctx);
         }
+        true
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if let Some(error) = &self.error {
+            let canvas = self.fallback_canvas.cast::<HtmlCanvasElement>().unwrap();
+            show_message(&canvas, error, None);
+            show_poster(&canvas, &self.shader_name, error);
+            return;
+        }
+        if !first_render {
+            return;
+        }
+        let canvas = self.canvas.cast::<HtmlCanvasElement>().unwrap();
+        let on_change = ctx.link().callback(Msg::SetActive);
+        self.visibility = Some(VisibilityListener::set(&canvas, on_change));
+        let props = ctx.props();
+        if props.worker && !props.standalone {
+            self.worker = WorkerRenderer::spawn(
+                &canvas,
+                &self.shader_name,
+                props.fade_duration(),
+                ctx.link().callback(Msg::Error),
+                ctx.link().callback(Msg::WorkerFailed),
+            );
+        }
+        // the main thread renders where `OffscreenCanvas` is unsupported
+        if self.worker.is_none() {
+            self.init_context(ctx);
+        }
+        self.set_render_loop(ctx);
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
-        let Msg::Render(timestamp) = msg;
-        if let (Some(gl), Some(pipeline)) = (&self.gl, &mut self.pipeline) {
-            let canvas = self.canvas.cast::<HtmlCanvasElement>().unwrap();
-            if correct_canvas_size(&canvas, pipeline.pixel_ratio) {
-                if let Some(gl) = &self.gl {
-                    gl.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
+        let timestamp = match msg {
+            Msg::Render(_) if self.error.is_some() => return false,
+            Msg::Render(timestamp) => timestamp,
+            Msg::Error(error) => {
+                self.error = Some(error);
+                self.render_loop = None;
+                return true;
+            }
+            Msg::WorkerFailed(false) => {
+                self.worker = None;
+                self.init_context(ctx);
+                return false;
+            }
+            Msg::WorkerFailed(true) => {
+                self.error = Some(String::from("Failed to start the worker..."));
+                self.render_loop = None;
+                self.worker = None;
+                return true;
+            }
+            Msg::SetActive(true) => {
+                self.resume(ctx);
+                return false;
+            }
+            Msg::SetActive(false) => {
+                self.pause();
+                return false;
+            }
+            Msg::ContextLost => {
+                // every WebGL object is invalid, and the media would keep playing
+                self.context_lost = true;
+                self.pause();
+                if let Some(renderer) = self.renderer.take() {
+                    renderer.stop_media();
                 }
+                return false;
+            }
+            Msg::ContextRestored => {
+                self.context_lost = false;
+                self.redraw = true;
+                self.init_renderer(ctx);
+                self.resume(ctx);
+                return false;
+            }
+            Msg::HotShader(shader) => return self.reload_shader(shader),
+            Msg::Edit(idx, source) => {
+                // the editor counts the lines from its first line
+                let source_lines = (1..=source.lines().count() as u32).collect::<Vec<_>>();
+                match self.recompile_pass(idx, &source, &source_lines) {
+                    Some(Ok(())) => {
+                        self.edit_errors.remove(&idx);
+                        self.redraw = true;
+                    }
+                    Some(Err(e)) => {
+                        let (message, lines) = (e.to_string(), e.source_lines);
+                        self.edit_errors.insert(idx, EditError { message, lines });
+                    }
+                    None => return false,
+                }
+                return true;
+            }
+            Msg::Clock(clock) => {
+                self.set_clock(clock);
+                return true;
             }
-            if self.frame_count % 2 == 0 {
-                let resolution = [canvas.width() as f32, canvas.height() as f32];
-                let mouse_position = [
-                    self.mouse_listener.mouse_position[0].load(Ordering::SeqCst) as f32,
-                    self.mouse_listener.mouse_position[1].load(Ordering::SeqCst) as f32,
-                ];
-                webgl::gl_rendering(
-                    gl,
-                    pipeline,
-                    resolution,
-                    (self.init_time + timestamp * 0.001) as f32,
-                    mouse_position,
+            Msg::Shortcut(key) => return self.shortcut(&key),
+        };
+        let timestamp = timestamp - self.paused_time;
+        let time = self
+            .stopped_time
+            .unwrap_or_else(|| self.running_time(timestamp));
+        let frozen = self.stopped_time.is_some() && !self.redraw;
+        let mut rendered = false;
+        if let Some(worker) = &mut self.worker {
+            let canvas_size = canvas_size();
+            // the fade goes on while `iTime` is paused
+            let frozen = frozen && !worker.fading();
+            if worker.ready() && (!frozen || worker.resized(canvas_size)) {
+                let (touches, touch_count) = self.mouse_listener.touch_uniform();
+                worker.post_frame(&FrameMessage {
+                    timestamp,
+                    time,
+                    canvas_size,
+                    client_width: gloo::utils::document_element().client_width(),
+                    mouse: self.mouse_listener.uniform(),
+                    touches,
+                    touch_count,
+                    seed: self.seed,
+                });
+                self.redraw = false;
+            }
+        } else if let Some(renderer) = &mut self.renderer {
+            let canvas = self.canvas.cast::<HtmlCanvasElement>().unwrap();
+            // resizing clears the canvas
+            let resized = correct_canvas_size(&canvas);
+            let frozen = frozen && !renderer.fading();
+            if !frozen || resized {
+                let canvas_size = [canvas.width(), canvas.height()];
+                let client_width = gloo::utils::document_element().client_width();
+                rendered = renderer.render(
+                    timestamp,
+                    time,
+                    canvas_size,
+                    client_width,
+                    &self.mouse_listener,
+                    self.seed,
                 );
+                if rendered {
+                    self.redraw = false;
+                }
             }
-            self.frame_count += 1;
         }
-        self.set_render_loop(ctx);
-        false
+        match self.animating() {
+            true => self.set_render_loop(ctx),
+            // the last frame stays on the canvas, e.g. the still frame for reduced motion
+            false => self.pause(),
+        }
+        // the time of the editor
+        rendered && ctx.props().edit
     }
 }
 
 impl BackGround {
+    /// Gets the context of the canvas to render on the main thread.
+    fn init_context(&mut self, ctx: &Context<Self>) {
+        let canvas = self.canvas.cast::<HtmlCanvasElement>().unwrap();
+        self.gl = webgl::get_context(&canvas);
+        match self.gl {
+            Some(_) => {
+                self._context_listeners = listen_context_loss(ctx, &canvas);
+                self.init_renderer(ctx);
+                if cfg!(debug_assertions) {
+                    self._hot_reload = HotReload::connect(ctx.link().callback(Msg::HotShader));
+                }
+            }
+            None => ctx
+                .link()
+                .send_message(Msg::Error(String::from("Failed to init WebGL..."))),
+        }
+    }
+
+    /// Creates the buffers, programs and textures, on the first render and after a context loss.
+    fn init_renderer(&mut self, ctx: &Context<Self>) {
+        if let Some(gl) = &self.gl {
+            let fade_duration = ctx.props().fade_duration();
+            self.renderer = Some(Renderer::new(gl.clone(), fade_duration));
+            self.create_render_graph(ctx, false);
+        }
+    }
+
+    /// Replaces the render graph with the one of `shader_name`, in the worker if it renders, clearing the error.
+    /// The previous render graph fades out unless the background is still, e.g. hidden or for reduced motion,
+    /// or it failed.
+    fn change_render_graph(&mut self, ctx: &Context<Self>) {
+        self.shader_errors.clear();
+        self.edit_errors.clear();
+        self.redraw = true;
+        let fade = self.error.take().is_none() && self.animating();
+        if let Some(worker) = &self.worker {
+            worker.post_shader(&self.shader_name, fade);
+        } else if !self.context_lost {
+            // a lost context creates it on the restore
+            self.create_render_graph(ctx, fade);
+        }
+    }
+
+    fn create_render_graph(&mut self, ctx: &Context<Self>, fade: bool) {
+        let renderer = match &mut self.renderer {
+            Some(renderer) => renderer,
+            None => return,
+        };
+        if let Some(shader) = get_shader(&self.shader_name) {
+            match render_graph::create_render_graph(&renderer.gl, shader) {
+                Ok(render_graph) => {
+                    if self.audio_playing {
+                        render_graph.set_audio_playing(true);
+                    }
+                    renderer.set_render_graph(render_graph, fade);
+                }
+                Err(e) => {
+                    gloo::console::error!(format!("{e}"));
+                    ctx.link().send_message(Msg::Error(format!("{e}")));
+                }
+            }
+        } else {
+            gloo::utils::window()
+                .alert_with_message("failed to load shader")
+                .expect_throw("failed to show alert");
+        }
+    }
+
+    /// Compiles a pass of the render graph from `source` in place, keeping the channels, the buffers and `iTime`.
+    /// `None` if there is no such pass to replace.
+    fn recompile_pass(
+        &mut self,
+        idx: usize,
+        source: &str,
+        source_lines: &[u32],
+    ) -> Option<Result<(), ShaderError>> {
+        let (gl, render_graph) = match &mut self.renderer {
+            Some(Renderer {
+                gl,
+                render_graph: Some(render_graph),
+                ..
+            }) => (gl, render_graph),
+            _ => return None,
+        };
+        let shader = webgl::ShaderSource {
+            source,
+            source_lines,
+            ..*get_shader(&self.shader_name)?.passes.get(idx)?
+        };
+        Some(render_graph.reload_pass(gl, idx, shader))
+    }
+
+    /// Replaces a pass with the one pushed by the watcher.
+    /// On errors the previous program keeps running under the messages.
+    fn reload_shader(&mut self, shader: HotShader) -> bool {
+        // the other shaders are pushed too
+        let idx = get_shader(&self.shader_name).and_then(|built| {
+            built
+                .passes
+                .iter()
+                .position(|pass| pass.path == shader.path())
+        });
+        let idx = match idx {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let res = match shader {
+            HotShader::Source {
+                source,
+                source_lines,
+                ..
+            } => match self.recompile_pass(idx, &source, &source_lines) {
+                Some(res) => res.map_err(|e| e.to_string()),
+                None => return false,
+            },
+            HotShader::Error { message, .. } => Err(message),
+        };
+        match res {
+            Ok(()) => {
+                self.shader_errors.remove(&idx);
+            }
+            Err(e) => {
+                gloo::console::error!(e.clone());
+                self.shader_errors.insert(idx, e);
+            }
+        }
+        true
+    }
+
+    /// `iTime` at `timestamp` without the pauses while the clock is not stopped
+    fn running_time(&self, timestamp: f64) -> f64 {
+        self.init_time + timestamp * 0.001 * self.speed
+    }
+
+    /// Pauses, steps, seeks or changes the speed of `iTime`,
+    /// and writes a paused frame to the address.
+    fn set_clock(&mut self, clock: Clock) {
+        let timestamp = performance_now() - self.paused_time;
+        let running = self.running_time(timestamp);
+        let current = self.stopped_time.unwrap_or(running);
+        match clock {
+            Clock::Play => {
+                if let Some(stopped) = self.stopped_time.take() {
+                    self.init_time += stopped - running;
+                }
+            }
+            Clock::Pause => self.stopped_time = Some(current),
+            Clock::Step(seconds) => {
+                self.stopped_time = Some(current + seconds);
+                self.redraw = true;
+            }
+            Clock::Seek(time) => match self.stopped_time {
+                Some(_) => {
+                    self.stopped_time = Some(time);
+                    self.redraw = true;
+                }
+                None => self.init_time += time - running,
+            },
+            Clock::Speed(speed) => {
+                self.speed = speed;
+                // keeps the current time
+                self.init_time = running - timestamp * 0.001 * speed;
+            }
+        }
+        self.write_time_query();
+    }
+
+    /// Space plays or pauses, the arrows (or `,` and `.`) step a frame, `[` and `]` halve or double the speed
+    /// and `Home` (or `0`) seeks to the start, unless a pass reads the keyboard.
+    fn shortcut(&mut self, key: &str) -> bool {
+        match self.render_graph() {
+            Some(render_graph) if render_graph.keyboard.is_none() => {}
+            _ => return false,
+        }
+        let clock = match key {
+            " " => match self.stopped_time {
+                Some(_) => Clock::Play,
+                None => Clock::Pause,
+            },
+            "ArrowRight" | "." => Clock::Step(editor::STEP_TIME),
+            "ArrowLeft" | "," => Clock::Step(-editor::STEP_TIME),
+            "]" => Clock::Speed(self.speed * 2.0),
+            "[" => Clock::Speed(self.speed * 0.5),
+            "Home" | "0" => Clock::Seek(0.0),
+            _ => return false,
+        };
+        self.set_clock(clock);
+        true
+    }
+
+    /// Replaces `TIME_QUERY_KEYS` in the address, with `t` and `paused=1` only while the clock is stopped,
+    /// so that the address reproduces the paused frame.
+    fn write_time_query(&self) {
+        use qstring::QString;
+        let win = gloo::utils::window();
+        let search = win.location().search().expect_throw("failed to get query");
+        let mut pairs = QString::from(search.as_str()).into_pairs();
+        pairs.retain(|(key, _)| !TIME_QUERY_KEYS.contains(&key.as_str()));
+        if let Some(time) = self.stopped_time {
+            pairs.push((String::from("t"), time.to_string()));
+            pairs.push((String::from("paused"), String::from("1")));
+        }
+        if self.speed != 1.0 {
+            pairs.push((String::from("speed"), self.speed.to_string()));
+        }
+        pairs.push((String::from("seed"), self.seed.to_string()));
+        // the query alone would be resolved from `<base>`
+        let path = win.location().pathname().expect_throw("failed to get path");
+        let url = format!("{path}?{}", QString::new(pairs));
+        win.history()
+            .and_then(|history| {
+                history.replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(&url))
+            })
+            .unwrap_or_else(|e| gloo::console::log!(format!("{e:?}")));
+    }
+
+    fn render_graph(&self) -> Option<&RenderGraph> {
+        self.renderer.as_ref()?.render_graph.as_ref()
+    }
+
+    fn animating(&self) -> bool {
+        !self.context_lost
+            && self
+                .visibility
+                .as_ref()
+                .is_none_or(VisibilityListener::active)
+    }
+
+    fn pause(&mut self) {
+        self.render_loop = None;
+        self.pause_started.get_or_insert_with(performance_now);
+    }
+
+    /// Restarts the loop stopped by `pause`, skipping the paused time.
+    fn resume(&mut self, ctx: &Context<Self>) {
+        if !self.animating() || self.error.is_some() {
+            return;
+        }
+        if let Some(pause_started) = self.pause_started.take() {
+            self.paused_time += performance_now() - pause_started;
+            self.set_render_loop(ctx);
+        }
+    }
+
     fn set_render_loop(&mut self, ctx: &Context<Self>) {
         let link = ctx.link().clone();
         let handle =
@@ -157,13 +800,123 @@ impl BackGround {
     }
 }
 
-fn correct_canvas_size(canvas: &HtmlCanvasElement, pixel_ratio: u32) -> bool {
+/// the clock of the timestamps of `requestAnimationFrame`
+fn performance_now() -> f64 {
+    gloo::utils::window()
+        .performance()
+        .expect_throw("failed to get performance")
+        .now()
+}
+
+/// `preventDefault` on `webglcontextlost` tells the browser to restore the context.
+fn listen_context_loss(
+    ctx: &Context<BackGround>,
+    canvas: &HtmlCanvasElement,
+) -> Vec<EventListener> {
+    let options = gloo::events::EventListenerOptions::enable_prevent_default();
+    let link = ctx.link().clone();
+    let on_lost = move |e: &Event| {
+        e.prevent_default();
+        link.send_message(Msg::ContextLost);
+    };
+    let link = ctx.link().clone();
+    let on_restored = move |_: &Event| link.send_message(Msg::ContextRestored);
+    vec![
+        EventListener::new_with_options(canvas, "webglcontextlost", options, on_lost),
+        EventListener::new(canvas, "webglcontextrestored", on_restored),
+    ]
+}
+
+/// Sends the keys to `BackGround::shortcut`, except for the inputs of the editor.
+fn listen_shortcuts(ctx: &Context<BackGround>) -> EventListener {
+    let link = ctx.link().clone();
+    let win = gloo::utils::window();
+    EventListener::new(&win, "keydown", move |e| {
+        let e = KeyboardEvent::from(wasm_bindgen::JsValue::from(e.clone()));
+        let tag_name = e
+            .target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .map(|element| element.tag_name());
+        let editing = matches!(
+            tag_name.as_deref(),
+            Some("BUTTON" | "INPUT" | "SELECT" | "TEXTAREA")
+        );
+        if !editing && !e.ctrl_key() && !e.meta_key() && !e.alt_key() {
+            link.send_message(Msg::Shortcut(e.key()));
+        }
+    })
+}
+
+/// the device pixels of the window
+fn canvas_size() -> [u32; 2] {
     let doc = gloo::utils::document_element();
-    let resized = canvas.width() != doc.client_width() as u32 / pixel_ratio
-        || canvas.height() != doc.client_height() as u32 / pixel_ratio;
+    let pixel_ratio = gloo::utils::window().device_pixel_ratio();
+    let width = (doc.client_width() as f64 * pixel_ratio).round() as u32;
+    let height = (doc.client_height() as f64 * pixel_ratio).round() as u32;
+    [width, height]
+}
+
+/// Matches the canvas to the device pixels of the window, `true` if it is resized.
+fn correct_canvas_size(canvas: &HtmlCanvasElement) -> bool {
+    let [width, height] = canvas_size();
+    let resized = canvas.width() != width || canvas.height() != height;
     if resized {
-        canvas.set_width(doc.client_width() as u32 / pixel_ratio);
-        canvas.set_height(doc.client_height() as u32 / pixel_ratio);
+        canvas.set_width(width);
+        canvas.set_height(height);
     }
     resized
 }
+
+/// Draws `posters/{shader_name}.png` behind the message once it is loaded.
+/// A missing poster leaves the message alone.
+fn show_poster(canvas: &HtmlCanvasElement, shader_name: &str, message: &str) {
+    let shader_name = match shaders::get_texts().contains_key(&shader_name) {
+        true => shader_name,
+        false => "default",
+    };
+    let image = HtmlImageElement::new().expect_throw("failed to create Image element");
+    let (canvas, cloned_image, message) = (canvas.clone(), image.clone(), message.to_string());
+    EventListener::once(&image, "load", move |_| {
+        show_message(&canvas, &message, Some(&cloned_image))
+    })
+    .forget();
+    image.set_src(&format!("./posters/{shader_name}.png"));
+}
+
+fn show_message(canvas: &HtmlCanvasElement, message: &str, poster: Option<&HtmlImageElement>) {
+    let doc = gloo::utils::document_element();
+    canvas.set_width(doc.client_width() as u32);
+    canvas.set_height(doc.client_height() as u32);
+    let ctx: CanvasRenderingContext2d = { || canvas.get_context("2d").ok()??.dyn_into().ok() }()
+        .expect_throw("failed to init rendering context 2d");
+    if let Some(poster) = poster {
+        // covers the canvas like `object-fit: cover`
+        let (width, height) = (canvas.width() as f64, canvas.height() as f64);
+        let (poster_width, poster_height) = (
+            poster.natural_width() as f64,
+            poster.natural_height() as f64,
+        );
+        let scale = (width / poster_width).max(height / poster_height);
+        let (dw, dh) = (poster_width * scale, poster_height * scale);
+        ctx.draw_image_with_html_image_element_and_dw_and_dh(
+            poster,
+            (width - dw) * 0.5,
+            (height - dh) * 0.5,
+            dw,
+            dh,
+        )
+        .unwrap_or_else(|e| gloo::console::log!(format!("{e:?}")));
+    }
+    let lines = message.lines().collect::<Vec<_>>();
+    let (font_size, line_height) = match lines.len() {
+        1 => (30, 30.0),
+        _ => (16, 20.0),
+    };
+    ctx.set_font(&format!("{font_size}px serif"));
+    let bottom = canvas.height() as f64 - 30.0;
+    lines.iter().enumerate().for_each(|(idx, line)| {
+        let y = bottom - (lines.len() - 1 - idx) as f64 * line_height;
+        ctx.fill_text(line, 30.0, y)
+            .unwrap_or_else(|e| gloo::console::log!(format!("{e:?}")));
+    });
+}
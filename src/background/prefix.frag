@@ -0,0 +1,2 @@
+#version 300 es
+precision highp float;uniform vec3 iResolution;uniform float iTime;uniform float iTimeDelta;uniform int iFrame;uniform float iFrameRate;uniform vec4 iMouse;uniform vec4 iTouch[4];uniform int iTouchCount;uniform vec4 iDate;uniform float iSampleRate;uniform float iSeed;uniform float iChannelTime[4];uniform vec3 iChannelResolution[4];out vec4 outColor;void mainImage(out vec4,in vec2);void main(){mainImage(outColor,gl_FragCoord.xy);}
\ No newline at end of file
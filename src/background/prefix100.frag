@@ -0,0 +1,6 @@
+#extension GL_OES_standard_derivatives : enable
+#extension GL_EXT_shader_texture_lod : enable
+precision highp float;
+#define texture texture2D
+#define textureLod texture2DLodEXT
+uniform vec3 iResolution;uniform float iTime;uniform float iTimeDelta;uniform int iFrame;uniform float iFrameRate;uniform vec4 iMouse;uniform vec4 iTouch[4];uniform int iTouchCount;uniform vec4 iDate;uniform float iSampleRate;uniform float iSeed;uniform float iChannelTime[4];uniform vec3 iChannelResolution[4];void mainImage(out vec4,in vec2);void main(){vec4 color=vec4(0,0,0,1);mainImage(color,gl_FragCoord.xy);gl_FragColor=color;}
\ No newline at end of file
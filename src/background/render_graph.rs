@@ -0,0 +1,244 @@
+use super::channel::Channel;
+use super::keyboard::Keyboard;
+use super::webgl::{self, Pipeline, RenderGraphSource, ShaderError, ShaderSource, UpscalePipeline};
+use super::*;
+use WebGl2RenderingContext as GL;
+
+/// Passes of a shader, rendered in `order` every frame.
+///
+/// Each buffer pass renders into the back texture of its `FrameBuffer` and then swaps it to the front,
+/// so a pass reads the current frame of the buffers rendered before it and the previous frame of the others,
+/// including itself.
+#[derive(Debug)]
+pub struct RenderGraph {
+    pub passes: Vec<Pipeline>,
+    /// the image pass is drawn to the canvas if it is `None`, otherwise upscaled from its buffer
+    pub buffers: Vec<Option<FrameBuffer>>,
+    pub order: Vec<usize>,
+    pub upscale: UpscalePipeline,
+    /// listens to the keys only if a pass reads them
+    pub keyboard: Option<Keyboard>,
+    float_buffer: bool,
+}
+
+#[derive(Debug)]
+pub struct FrameBuffer {
+    targets: [(WebGlFramebuffer, WebGlTexture); 2],
+    front: usize,
+    size: [i32; 2],
+}
+
+pub fn create_render_graph(gl: &GL, source: RenderGraphSource) -> Result<RenderGraph, ShaderError> {
+    let upscale = webgl::create_upscale_pipeline(gl)?;
+    let passes = source
+        .passes
+        .iter()
+        .map(|shader| webgl::create_pipeline(gl, *shader))
+        .collect::<Result<Vec<_>, _>>()
+        .inspect_err(|_| upscale.delete(gl))?;
+    let image = passes.len() - 1;
+    let buffers = (0..passes.len()).map(|_| None).collect();
+    let mut order = Vec::new();
+    (0..passes.len()).for_each(|idx| sort_passes(&passes, image, idx, &mut order));
+    // RGBA16F render targets are an extension even in WebGL2
+    let float_buffer = matches!(gl.get_extension("EXT_color_buffer_float"), Ok(Some(_)));
+    let keyboard = passes
+        .iter()
+        .flat_map(|pass| pass.channels.iter().flatten())
+        .any(|binding| matches!(binding.channel, Channel::Keyboard))
+        .then(|| Keyboard::new(gl))
+        .flatten();
+    Ok(RenderGraph {
+        passes,
+        buffers,
+        order,
+        upscale,
+        keyboard,
+        float_buffer,
+    })
+}
+
+/// depth first search: a pass is pushed after the buffers it reads
+fn sort_passes(passes: &[Pipeline], image: usize, idx: usize, order: &mut Vec<usize>) {
+    if order.contains(&idx) {
+        return;
+    }
+    order.push(idx);
+    passes[idx]
+        .channels
+        .iter()
+        .flatten()
+        .for_each(|binding| match binding.channel {
+            Channel::Buffer(buffer) if buffer != idx && buffer != image => {
+                sort_passes(passes, image, buffer, order)
+            }
+            _ => {}
+        });
+    // move this pass behind its dependencies
+    order.retain(|i| *i != idx);
+    order.push(idx);
+}
+
+impl RenderGraph {
+    /// Stops the videos and tracks, which keep playing after the graph is dropped.
+    pub fn stop_media(&self) {
+        self.passes
+            .iter()
+            .flat_map(|pass| pass.channels.iter().flatten())
+            .for_each(|binding| match &binding.channel {
+                Channel::Video(video) => video.stop(),
+                Channel::Audio(audio) => audio.stop(),
+                _ => {}
+            });
+    }
+
+    /// Deletes the programs, the frame buffers and the textures, and stops the media,
+    /// e.g. when the shader of the background changes.
+    pub fn delete(self, gl: &GL) {
+        self.stop_media();
+        self.passes.iter().for_each(|pass| {
+            gl.delete_program(Some(&pass.program));
+            pass.channels.iter().flatten().for_each(|binding| {
+                // WebGL1 has no samplers nor `deleteSampler`
+                if let Some(sampler) = &binding.sampler {
+                    gl.delete_sampler(Some(sampler));
+                }
+                match &binding.channel {
+                    Channel::Texture(texture) => gl.delete_texture(Some(texture.texture())),
+                    Channel::Video(video) => gl.delete_texture(Some(video.texture())),
+                    Channel::Audio(audio) => gl.delete_texture(Some(audio.texture())),
+                    Channel::Buffer(_) | Channel::Keyboard => {}
+                }
+            });
+        });
+        self.buffers
+            .into_iter()
+            .flatten()
+            .for_each(|buffer| buffer.delete(gl));
+        if let Some(keyboard) = &self.keyboard {
+            gl.delete_texture(Some(keyboard.texture()));
+        }
+        self.upscale.delete(gl);
+    }
+
+    /// Compiles a pass from `shader` in place, keeping its channels and frame buffers.
+    pub fn reload_pass(
+        &mut self,
+        gl: &GL,
+        idx: usize,
+        shader: ShaderSource<'_>,
+    ) -> Result<(), ShaderError> {
+        let pipeline = webgl::recompile_pipeline(gl, &self.passes[idx], shader)?;
+        gl.delete_program(Some(&self.passes[idx].program));
+        self.passes[idx] = pipeline;
+        Ok(())
+    }
+
+    /// Plays or mutes the audio channels of all passes.
+    pub fn set_audio_playing(&self, playing: bool) {
+        self.passes
+            .iter()
+            .flat_map(|pass| pass.channels.iter().flatten())
+            .for_each(|binding| {
+                if let Channel::Audio(audio) = &binding.channel {
+                    audio.set_playing(playing);
+                }
+            });
+    }
+
+    /// Creates or resizes the frame buffers of the buffer passes, and of the image pass if it is `upscale`d.
+    /// Resizing clears the buffers.
+    pub fn prepare_buffers(&mut self, gl: &GL, size: [i32; 2], upscale: bool) {
+        let image = self.passes.len() - 1;
+        let float_buffer = self.float_buffer;
+        if !upscale {
+            if let Some(buffer) = self.buffers[image].take() {
+                buffer.delete(gl);
+            }
+        }
+        self.buffers
+            .iter_mut()
+            .enumerate()
+            .filter(|(idx, _)| *idx != image || upscale)
+            .for_each(|(_, buffer)| {
+                if buffer.as_ref().map(|buffer| buffer.size) != Some(size) {
+                    if let Some(buffer) = buffer.take() {
+                        buffer.delete(gl);
+                    }
+                    *buffer = Some(FrameBuffer::new(gl, size, float_buffer));
+                }
+            });
+    }
+}
+
+impl FrameBuffer {
+    fn new(gl: &GL, size: [i32; 2], float_buffer: bool) -> Self {
+        let create_target = || {
+            let texture = gl
+                .create_texture()
+                .expect_throw("failed to create texture pointer");
+            let (internal_format, ty) = match float_buffer {
+                true => (GL::RGBA16F, GL::HALF_FLOAT),
+                // unsized for WebGL1
+                false => (GL::RGBA, GL::UNSIGNED_BYTE),
+            };
+            gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+            gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                GL::TEXTURE_2D,
+                0,
+                internal_format as i32,
+                size[0],
+                size[1],
+                0,
+                GL::RGBA,
+                ty,
+                None,
+            )
+            .unwrap_or_else(|e| panic!("{e:?}"));
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+            gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+            gl.bind_texture(GL::TEXTURE_2D, None);
+
+            let framebuffer = gl
+                .create_framebuffer()
+                .expect_throw("failed to create framebuffer pointer");
+            gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&framebuffer));
+            gl.framebuffer_texture_2d(
+                GL::FRAMEBUFFER,
+                GL::COLOR_ATTACHMENT0,
+                GL::TEXTURE_2D,
+                Some(&texture),
+                0,
+            );
+            gl.clear(GL::COLOR_BUFFER_BIT);
+            gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+            (framebuffer, texture)
+        };
+        Self {
+            targets: [create_target(), create_target()],
+            front: 0,
+            size,
+        }
+    }
+
+    fn delete(self, gl: &GL) {
+        self.targets.iter().for_each(|(framebuffer, texture)| {
+            gl.delete_framebuffer(Some(framebuffer));
+            gl.delete_texture(Some(texture));
+        });
+    }
+
+    pub fn front_texture(&self) -> &WebGlTexture {
+        &self.targets[self.front].1
+    }
+
+    pub fn back_framebuffer(&self) -> &WebGlFramebuffer {
+        &self.targets[1 - self.front].0
+    }
+
+    pub fn swap(&mut self) {
+        self.front = 1 - self.front;
+    }
+}
@@ -0,0 +1,173 @@
+use super::render_graph::RenderGraph;
+use super::resolution::ResolutionController;
+use super::webgl;
+use super::*;
+use WebGl2RenderingContext as GL;
+
+/// Pointer state for `iMouse` and `iTouch` in the coordinates of `gl_FragCoord` in CSS pixels.
+pub trait Pointer {
+    fn uniform(&self) -> [f32; 4];
+    fn touch_uniform(&self) -> ([[f32; 4]; TOUCH_COUNT], i32);
+}
+
+/// The render graph of the shader on a WebGL context, cross-faded from the previous one.
+/// `BackGround` drives it on the main thread, or the worker on the frames posted by `WorkerRenderer`.
+#[derive(Debug)]
+pub struct Renderer {
+    pub gl: GL,
+    pub render_graph: Option<RenderGraph>,
+    /// the render graph of the previous shader until the cross-fade ends
+    fading: Option<Fading>,
+    /// the seconds of the cross-fade
+    fade_duration: f64,
+    resolution_controller: ResolutionController,
+    /// `iTime` of the previous rendered frame
+    pub previous_time: Option<f64>,
+    /// `iFrame`, counts only rendered frames
+    frame: i32,
+}
+
+/// The render graph of the previous shader, mixed under the new one until the fade ends.
+#[derive(Debug)]
+struct Fading {
+    render_graph: RenderGraph,
+    /// `iFrame` of the previous shader, which goes on during the fade
+    frame: i32,
+    /// the timestamp of the first frame of the fade
+    started: Option<f64>,
+}
+
+impl Renderer {
+    /// Initializes the context, on the first render and after a context loss.
+    pub fn new(gl: GL, fade_duration: f64) -> Self {
+        webgl::init_gl(&gl);
+        Self {
+            resolution_controller: ResolutionController::new(&gl),
+            gl,
+            render_graph: None,
+            fading: None,
+            fade_duration,
+            previous_time: None,
+            frame: 0,
+        }
+    }
+
+    /// Replaces the render graph, starting from the first frame at the same `iTime`.
+    /// The previous one fades out over `fade_duration` if `fade`, e.g. unless the background is still.
+    pub fn set_render_graph(&mut self, render_graph: RenderGraph, fade: bool) {
+        // a fade in progress is cut by the next one
+        if let Some(fading) = self.fading.take() {
+            fading.render_graph.delete(&self.gl);
+        }
+        if let Some(previous) = self.render_graph.replace(render_graph) {
+            match fade && self.fade_duration > 0.0 {
+                true => {
+                    self.fading = Some(Fading {
+                        render_graph: previous,
+                        frame: self.frame,
+                        started: None,
+                    })
+                }
+                false => previous.delete(&self.gl),
+            }
+        }
+        self.frame = 0;
+        self.previous_time = None;
+    }
+
+    /// whether the cross-fade goes on, also while `iTime` is paused
+    pub fn fading(&self) -> bool {
+        self.fading.is_some()
+    }
+
+    /// Stops the media when the context is lost, whose WebGL objects are all invalid.
+    pub fn stop_media(self) {
+        let fading = self.fading.map(|fading| fading.render_graph);
+        self.render_graph
+            .iter()
+            .chain(&fading)
+            .for_each(RenderGraph::stop_media);
+    }
+
+    /// Renders a frame of `canvas_size` unless the resolution controller skips it, `true` if rendered.
+    /// `client_width` is the width of the canvas in CSS pixels.
+    pub fn render(
+        &mut self,
+        timestamp: f64,
+        time: f64,
+        canvas_size: [u32; 2],
+        client_width: i32,
+        pointer: &impl Pointer,
+        seed: u32,
+    ) -> bool {
+        let (gl, controller) = (&self.gl, &mut self.resolution_controller);
+        let render_graph = match &mut self.render_graph {
+            Some(render_graph) => render_graph,
+            None => return false,
+        };
+        if !controller.begin_frame(gl, timestamp) {
+            return false;
+        }
+        let [width, height] = controller.render_size(canvas_size);
+        // pointer positions are in CSS pixels
+        let pointer_scale = width as f32 / client_width.max(1) as f32;
+        let (touches, touch_count) = pointer.touch_uniform();
+        let time_delta = self.previous_time.map_or(0.0, |previous| time - previous);
+        let uniforms = webgl::FrameUniforms {
+            resolution: [width as f32, height as f32],
+            time: time as f32,
+            time_delta: time_delta as f32,
+            frame: self.frame,
+            frame_rate: match time_delta > 0.0 {
+                true => (1.0 / time_delta) as f32,
+                false => 0.0,
+            },
+            mouse: pointer.uniform().map(|v| v * pointer_scale),
+            touches: touches.map(|touch| touch.map(|v| v * pointer_scale)),
+            touch_count,
+            date: date_uniform(),
+            // Shadertoy's fixed value
+            sample_rate: 44100.0,
+            seed: seed as f32,
+        };
+        let fade = self.fading.as_mut().map(|fading| {
+            let started = *fading.started.get_or_insert(timestamp);
+            let progress = (timestamp - started) * 0.001 / self.fade_duration;
+            (fading, progress.clamp(0.0, 1.0) as f32)
+        });
+        match fade {
+            Some((fading, fade)) if fade < 1.0 => {
+                let fading_uniforms = webgl::FrameUniforms {
+                    frame: fading.frame,
+                    ..uniforms
+                };
+                let fading_graph = (&mut fading.render_graph, &fading_uniforms, fade);
+                webgl::gl_rendering(gl, render_graph, Some(fading_graph), &uniforms, canvas_size);
+                fading.frame += 1;
+            }
+            _ => {
+                if let Some(fading) = self.fading.take() {
+                    fading.render_graph.delete(gl);
+                }
+                webgl::gl_rendering(gl, render_graph, None, &uniforms, canvas_size);
+            }
+        }
+        controller.end_frame(gl);
+        self.previous_time = Some(time);
+        self.frame += 1;
+        true
+    }
+}
+
+/// `iDate`: year, month (from 0), day and seconds of the day
+fn date_uniform() -> [f32; 4] {
+    let date = Date::new(&Date::now().into());
+    let seconds = (date.get_hours() * 3600 + date.get_minutes() * 60 + date.get_seconds()) as f32
+        + date.get_milliseconds() as f32 * 0.001;
+    [
+        date.get_full_year() as f32,
+        date.get_month() as f32,
+        date.get_date() as f32,
+        seconds,
+    ]
+}
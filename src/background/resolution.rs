@@ -0,0 +1,167 @@
+use super::*;
+use std::collections::VecDeque;
+use WebGl2RenderingContext as GL;
+
+/// the frame rate the controller tries to keep
+const TARGET_FPS: f64 = 60.0;
+/// the lowest resolution relative to the device pixels
+const MIN_SCALE: f64 = 0.25;
+/// the longest frame pacing, rendering one of this many animation frames
+const MAX_INTERVAL: u32 = 4;
+/// the number of frame times averaged before each adjustment
+const SAMPLE_COUNT: u32 = 30;
+
+/// constants of `EXT_disjoint_timer_query_webgl2`
+const TIME_ELAPSED_EXT: u32 = 0x88BF;
+const GPU_DISJOINT_EXT: u32 = 0x8FBB;
+
+/// Scales the render resolution and paces the frames to keep `TARGET_FPS`.
+///
+/// Frame times are measured on the GPU by timer queries if available, otherwise by the intervals of
+/// `requestAnimationFrame`, which cannot be shorter than the refresh rate, so the resolution is raised
+/// as long as the frames keep up.
+/// Changing the resolution recreates the buffers, so the adjustments are averaged over `SAMPLE_COUNT` frames.
+#[derive(Debug)]
+pub struct ResolutionController {
+    timer: Option<GpuTimer>,
+    /// timestamp of the previous rendered frame
+    previous_timestamp: Option<f64>,
+    frame_count: u32,
+    /// sum of the frame times in milliseconds since the last adjustment
+    total_time: f64,
+    samples: u32,
+    /// the render resolution relative to the canvas
+    scale: f64,
+    /// renders one of this many animation frames
+    interval: u32,
+}
+
+#[derive(Debug)]
+struct GpuTimer {
+    active: Option<WebGlQuery>,
+    pending: VecDeque<WebGlQuery>,
+}
+
+impl ResolutionController {
+    pub fn new(gl: &GL) -> Self {
+        let timer = matches!(
+            gl.get_extension("EXT_disjoint_timer_query_webgl2"),
+            Ok(Some(_))
+        )
+        .then(|| GpuTimer {
+            active: None,
+            pending: VecDeque::new(),
+        });
+        Self {
+            timer,
+            previous_timestamp: None,
+            frame_count: 0,
+            total_time: 0.0,
+            samples: 0,
+            scale: 0.5,
+            interval: 1,
+        }
+    }
+
+    /// Called on every animation frame, returns whether to render this frame.
+    pub fn begin_frame(&mut self, gl: &GL, timestamp: f64) -> bool {
+        self.frame_count += 1;
+        if self.frame_count % self.interval != 0 {
+            return false;
+        }
+        let frame_time = match &mut self.timer {
+            Some(timer) => timer.poll(gl),
+            None => self.previous_timestamp.map(|previous| timestamp - previous),
+        };
+        self.previous_timestamp = Some(timestamp);
+        if let Some(frame_time) = frame_time {
+            // a paced frame can take the time of `interval` frames
+            self.add_sample(frame_time / self.interval as f64);
+        }
+        if let Some(timer) = &mut self.timer {
+            timer.begin(gl);
+        }
+        true
+    }
+
+    /// Called after the rendered frame is submitted.
+    pub fn end_frame(&mut self, gl: &GL) {
+        if let Some(timer) = &mut self.timer {
+            timer.end(gl);
+        }
+    }
+
+    /// the size of the render target for the canvas of `canvas_size`
+    pub fn render_size(&self, canvas_size: [u32; 2]) -> [u32; 2] {
+        canvas_size.map(|size| ((size as f64 * self.scale).round() as u32).max(1))
+    }
+
+    /// Lowers the resolution and then the frame rate if the frames are slow, and raises them in reverse.
+    fn add_sample(&mut self, frame_time: f64) {
+        self.total_time += frame_time;
+        self.samples += 1;
+        if self.samples < SAMPLE_COUNT {
+            return;
+        }
+        let budget = 1000.0 / TARGET_FPS;
+        let average = self.total_time / self.samples as f64;
+        (self.total_time, self.samples) = (0.0, 0);
+        // refresh intervals never go below the budget
+        let (fast, slow) = match self.timer {
+            Some(_) => (budget * 0.6, budget * 0.9),
+            None => (budget * 1.05, budget * 1.3),
+        };
+        if average > slow {
+            match self.scale > MIN_SCALE {
+                true => self.scale = (self.scale * 0.8).max(MIN_SCALE),
+                false => self.interval = (self.interval + 1).min(MAX_INTERVAL),
+            }
+        } else if average < fast {
+            match self.interval > 1 {
+                true => self.interval -= 1,
+                false => self.scale = (self.scale * 1.1).min(1.0),
+            }
+        }
+    }
+}
+
+impl GpuTimer {
+    /// Only a few frames are measured at once since the results come some frames later.
+    fn begin(&mut self, gl: &GL) {
+        if self.pending.len() >= 4 {
+            return;
+        }
+        if let Some(query) = gl.create_query() {
+            gl.begin_query(TIME_ELAPSED_EXT, &query);
+            self.active = Some(query);
+        }
+    }
+
+    fn end(&mut self, gl: &GL) {
+        if let Some(query) = self.active.take() {
+            gl.end_query(TIME_ELAPSED_EXT);
+            self.pending.push_back(query);
+        }
+    }
+
+    /// the elapsed time of the oldest finished query in milliseconds
+    fn poll(&mut self, gl: &GL) -> Option<f64> {
+        let query = self.pending.front()?;
+        let available = gl.get_query_parameter(query, GL::QUERY_RESULT_AVAILABLE);
+        if available.as_bool() != Some(true) {
+            return None;
+        }
+        let query = self.pending.pop_front()?;
+        let nanoseconds = gl.get_query_parameter(&query, GL::QUERY_RESULT).as_f64();
+        gl.delete_query(Some(&query));
+        // the results are invalid while the GPU is disjoint, e.g. on a power state change
+        let disjoint = gl
+            .get_parameter(GPU_DISJOINT_EXT)
+            .ok()
+            .is_none_or(|disjoint| disjoint.as_bool() != Some(false));
+        match disjoint {
+            true => None,
+            false => nanoseconds.map(|ns| ns * 1e-6),
+        }
+    }
+}
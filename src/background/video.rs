@@ -0,0 +1,129 @@
+use super::*;
+use wasm_bindgen_futures::JsFuture;
+use WebGl2RenderingContext as GL;
+
+/// `HTMLMediaElement.HAVE_CURRENT_DATA`
+const HAVE_CURRENT_DATA: u16 = 2;
+
+/// A texture re-uploaded from a playing video every frame.
+#[derive(Clone, Debug)]
+pub struct VideoTexture {
+    texture: WebGlTexture,
+    video: HtmlVideoElement,
+    vflip: bool,
+    mipmap: bool,
+}
+
+impl VideoTexture {
+    /// Plays a looped video file, muted so that it can start without user interaction.
+    pub fn from_url(gl: &GL, url: &str, vflip: bool, mipmap: bool) -> Option<VideoTexture> {
+        let res = VideoTexture::new(gl, vflip, mipmap)?;
+        res.video.set_loop(true);
+        res.video.set_src(url);
+        res.play();
+        Some(res)
+    }
+
+    /// Plays the camera after the permission of the user.
+    pub fn from_webcam(gl: &GL, vflip: bool, mipmap: bool) -> Option<VideoTexture> {
+        let res = VideoTexture::new(gl, vflip, mipmap)?;
+        let media_devices = gloo::utils::window().navigator().media_devices().ok()?;
+        let mut constraints = MediaStreamConstraints::new();
+        constraints.video(&true.into());
+        let promise = media_devices
+            .get_user_media_with_constraints(&constraints)
+            .ok()?;
+        let cloned_res = res.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match JsFuture::from(promise).await {
+                Ok(stream) => {
+                    let stream: MediaStream = stream.unchecked_into();
+                    cloned_res.video.set_src_object(Some(&stream));
+                    cloned_res.play();
+                }
+                Err(e) => gloo::console::error!(format!("failed to open webcam: {e:?}")),
+            }
+        });
+        Some(res)
+    }
+
+    fn new(gl: &GL, vflip: bool, mipmap: bool) -> Option<VideoTexture> {
+        let video: HtmlVideoElement = gloo::utils::document()
+            .create_element("video")
+            .expect_throw("failed to create video element")
+            .unchecked_into();
+        video.set_muted(true);
+        // iOS plays inline videos only
+        video
+            .set_attribute("playsinline", "")
+            .expect_throw("failed to set playsinline");
+        Some(VideoTexture {
+            texture: gl.create_texture()?,
+            video,
+            vflip,
+            mipmap,
+        })
+    }
+
+    fn play(&self) {
+        if let Ok(promise) = self.video.play() {
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(e) = JsFuture::from(promise).await {
+                    gloo::console::error!(format!("failed to play video: {e:?}"));
+                }
+            });
+        }
+    }
+
+    /// Stops the playback and releases the camera.
+    pub fn stop(&self) {
+        let _ = self.video.pause();
+        if let Some(stream) = self.video.src_object() {
+            stream
+                .get_tracks()
+                .iter()
+                .for_each(|track| MediaStreamTrack::from(track).stop());
+        }
+        self.video.set_src_object(None);
+    }
+
+    pub fn texture(&self) -> &WebGlTexture {
+        &self.texture
+    }
+
+    pub fn resolution(&self) -> [f32; 3] {
+        [
+            self.video.video_width() as f32,
+            self.video.video_height() as f32,
+            1.0,
+        ]
+    }
+
+    /// `iChannelTime`: the playback position
+    pub fn time(&self) -> f32 {
+        self.video.current_time() as f32
+    }
+
+    /// Uploads the current frame if the video has one.
+    pub fn upload(&self, gl: &GL) {
+        if self.video.ready_state() < HAVE_CURRENT_DATA {
+            return;
+        }
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.texture));
+        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, self.vflip as i32);
+        gl.tex_image_2d_with_u32_and_u32_and_html_video_element(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            &self.video,
+        )
+        .unwrap_or_else(|e| panic!("{e:?}"));
+        let size = [self.video.video_width(), self.video.video_height()];
+        if self.mipmap && channel::power_of_two(gl, size) {
+            gl.generate_mipmap(GL::TEXTURE_2D);
+        }
+        gl.bind_texture(GL::TEXTURE_2D, None);
+    }
+}
@@ -0,0 +1,101 @@
+use super::*;
+use wasm_bindgen::closure::Closure;
+
+/// Reasons to stop the render loop.
+#[derive(Debug, Default)]
+struct VisibilityState {
+    /// Page Visibility API, e.g. the tab is in the background
+    hidden: AtomicBool,
+    /// the canvas is out of the viewport of the top frame
+    off_screen: AtomicBool,
+    /// `prefers-reduced-motion: reduce`, only a still frame is rendered
+    reduced_motion: AtomicBool,
+}
+
+impl VisibilityState {
+    fn active(&self) -> bool {
+        !self.hidden.load(Ordering::SeqCst)
+            && !self.off_screen.load(Ordering::SeqCst)
+            && !self.reduced_motion.load(Ordering::SeqCst)
+    }
+}
+
+/// Calls `on_change` with whether the background should be animated.
+#[derive(Debug)]
+pub struct VisibilityListener {
+    state: Arc<VisibilityState>,
+    observer: IntersectionObserver,
+    _on_intersection: Closure<dyn FnMut(js_sys::Array)>,
+    _handlers: Vec<EventListener>,
+}
+
+impl VisibilityListener {
+    pub fn set(canvas: &HtmlCanvasElement, on_change: Callback<bool>) -> VisibilityListener {
+        let state: Arc<VisibilityState> = Default::default();
+        let document = gloo::utils::document();
+        state.hidden.store(document.hidden(), Ordering::SeqCst);
+        let mut _handlers = Vec::new();
+
+        let (cloned_state, cloned_on_change) = (Arc::clone(&state), on_change.clone());
+        let cloned_document = document.clone();
+        let on_visibility_change = move |_: &Event| {
+            let hidden = cloned_document.hidden();
+            cloned_state.hidden.store(hidden, Ordering::SeqCst);
+            cloned_on_change.emit(cloned_state.active());
+        };
+        _handlers.push(EventListener::new(
+            &document,
+            "visibilitychange",
+            on_visibility_change,
+        ));
+
+        let reduced_motion = gloo::utils::window()
+            .match_media("(prefers-reduced-motion: reduce)")
+            .ok()
+            .flatten();
+        if let Some(query) = reduced_motion {
+            state
+                .reduced_motion
+                .store(query.matches(), Ordering::SeqCst);
+            let (cloned_state, cloned_on_change) = (Arc::clone(&state), on_change.clone());
+            let cloned_query = query.clone();
+            _handlers.push(EventListener::new(&query, "change", move |_| {
+                let matches = cloned_query.matches();
+                cloned_state.reduced_motion.store(matches, Ordering::SeqCst);
+                cloned_on_change.emit(cloned_state.active());
+            }));
+        }
+
+        // the implicit root is the viewport
+        let cloned_state = Arc::clone(&state);
+        let on_intersection = Closure::wrap(Box::new(move |entries: js_sys::Array| {
+            let entry = entries.iter().last().map(IntersectionObserverEntry::from);
+            if let Some(entry) = entry {
+                let off_screen = !entry.is_intersecting();
+                cloned_state.off_screen.store(off_screen, Ordering::SeqCst);
+                on_change.emit(cloned_state.active());
+            }
+        }) as Box<dyn FnMut(js_sys::Array)>);
+        let observer = IntersectionObserver::new(on_intersection.as_ref().unchecked_ref())
+            .expect_throw("failed to create IntersectionObserver");
+        observer.observe(canvas);
+
+        VisibilityListener {
+            state,
+            observer,
+            _on_intersection: on_intersection,
+            _handlers,
+        }
+    }
+
+    /// whether the background should be animated
+    pub fn active(&self) -> bool {
+        self.state.active()
+    }
+}
+
+impl Drop for VisibilityListener {
+    fn drop(&mut self) {
+        self.observer.disconnect();
+    }
+}
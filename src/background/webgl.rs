@@ -1,109 +1,268 @@
+use super::channel::{self, Channel, ChannelBinding, ChannelSource, CHANNEL_COUNT};
+use super::render_graph::{FrameBuffer, RenderGraph};
 use super::*;
 use WebGl2RenderingContext as GL;
 
 #[derive(Clone, Debug)]
 pub struct Pipeline {
     pub program: WebGlProgram,
-    pub texture: Option<TextureInfo>,
+    pub channels: [Option<ChannelBinding>; CHANNEL_COUNT],
     pub position_location: u32,
     pub resolution_location: Option<WebGlUniformLocation>,
     pub time_location: Option<WebGlUniformLocation>,
+    pub time_delta_location: Option<WebGlUniformLocation>,
+    pub frame_location: Option<WebGlUniformLocation>,
+    pub frame_rate_location: Option<WebGlUniformLocation>,
     pub mouse_location: Option<WebGlUniformLocation>,
-    pub texture_location: Option<WebGlUniformLocation>,
+    pub touch_location: Option<WebGlUniformLocation>,
+    pub touch_count_location: Option<WebGlUniformLocation>,
+    pub date_location: Option<WebGlUniformLocation>,
+    pub sample_rate_location: Option<WebGlUniformLocation>,
+    pub seed_location: Option<WebGlUniformLocation>,
+    pub channel_time_location: Option<WebGlUniformLocation>,
+    pub channel_locations: [Option<WebGlUniformLocation>; CHANNEL_COUNT],
     pub texture_resolution_location: Option<WebGlUniformLocation>,
-    pub pixel_ratio: u32,
 }
 
+/// Shadertoy's inputs of a frame, except for the channels
 #[derive(Clone, Copy, Debug)]
-pub struct ShaderSource {
-    pub source: &'static str,
-    pub texture_url: Option<&'static str>,
+pub struct FrameUniforms {
+    pub resolution: [f32; 2],
+    pub time: f32,
+    pub time_delta: f32,
+    pub frame: i32,
+    pub frame_rate: f32,
+    /// xy: current position, zw: click position, z > 0 while pressed, w > 0 only on the clicked frame
+    pub mouse: [f32; 4],
+    /// not in Shadertoy, xy: current position, zw: start position of each pressed pointer
+    pub touches: [[f32; 4]; TOUCH_COUNT],
+    pub touch_count: i32,
+    /// year, month (from 0), day and seconds of the day
+    pub date: [f32; 4],
+    pub sample_rate: f32,
+    /// not in Shadertoy, fixed by `?seed=` to reproduce a frame
+    pub seed: f32,
+}
+
+/// A pass of a shader, `'static` if it is built and borrowed if it is edited at runtime.
+#[derive(Clone, Copy, Debug)]
+pub struct ShaderSource<'a> {
+    /// path from `shaders/`, e.g. `default.frag` or `foo/buffer_a.frag`
+    pub path: &'static str,
+    pub channels: [Option<ChannelSource>; CHANNEL_COUNT],
+    pub source: &'a str,
+    /// line numbers in the original `.frag` file of each line of `source`
+    pub source_lines: &'a [u32],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Link,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderGraphSource {
+    /// buffer passes followed by the image pass
+    pub passes: &'static [ShaderSource<'static>],
 }
 
 #[derive(Clone, Debug)]
-pub struct TextureInfo {
-    texture: WebGlTexture,
-    resolution: Arc<[AtomicU32; 2]>,
+pub struct ShaderError {
+    pub path: &'static str,
+    pub stage: ShaderStage,
+    pub log: String,
+    /// the lines in the original `.frag` file reported by the info log, in the order of the log
+    pub source_lines: Vec<u32>,
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stage = match self.stage {
+            ShaderStage::Vertex => "vertex shader",
+            ShaderStage::Fragment => "fragment shader",
+            ShaderStage::Link => "program link",
+        };
+        let path = self.path;
+        match self.source_lines.first() {
+            Some(line) => writeln!(f, "Failed to compile {stage} at {path}:{line}.")?,
+            None => writeln!(f, "Failed to compile {stage} of {path}.")?,
+        }
+        write!(f, "{}", self.log)
+    }
 }
 
-pub fn create_pipeline(gl: &GL, shader: ShaderSource) -> Pipeline {
-    let (program, texture) = prepare_program(gl, shader);
+pub fn create_pipeline(gl: &GL, shader: ShaderSource<'_>) -> Result<Pipeline, ShaderError> {
+    let program = prepare_program(gl, shader)?;
+    let channels = shader
+        .channels
+        .map(|source| source.and_then(|source| channel::bind_channel(gl, source)));
+    Ok(locate_uniforms(gl, program, channels))
+}
+
+/// Compiles `shader` again for `pipeline`, whose channels are kept instead of loaded again.
+pub fn recompile_pipeline(
+    gl: &GL,
+    pipeline: &Pipeline,
+    shader: ShaderSource<'_>,
+) -> Result<Pipeline, ShaderError> {
+    let program = prepare_program(gl, shader)?;
+    Ok(locate_uniforms(gl, program, pipeline.channels.clone()))
+}
+
+fn locate_uniforms(
+    gl: &GL,
+    program: WebGlProgram,
+    channels: [Option<ChannelBinding>; CHANNEL_COUNT],
+) -> Pipeline {
+    let channel_locations =
+        std::array::from_fn(|unit| gl.get_uniform_location(&program, &format!("iChannel{unit}")));
     Pipeline {
         position_location: gl.get_attrib_location(&program, "position") as u32,
         resolution_location: gl.get_uniform_location(&program, "iResolution"),
         time_location: gl.get_uniform_location(&program, "iTime"),
-        texture_location: gl.get_uniform_location(&program, "iChannel0"),
+        time_delta_location: gl.get_uniform_location(&program, "iTimeDelta"),
+        frame_location: gl.get_uniform_location(&program, "iFrame"),
+        frame_rate_location: gl.get_uniform_location(&program, "iFrameRate"),
         mouse_location: gl.get_uniform_location(&program, "iMouse"),
+        touch_location: gl.get_uniform_location(&program, "iTouch"),
+        touch_count_location: gl.get_uniform_location(&program, "iTouchCount"),
+        date_location: gl.get_uniform_location(&program, "iDate"),
+        sample_rate_location: gl.get_uniform_location(&program, "iSampleRate"),
+        seed_location: gl.get_uniform_location(&program, "iSeed"),
+        channel_time_location: gl.get_uniform_location(&program, "iChannelTime"),
         texture_resolution_location: gl.get_uniform_location(&program, "iChannelResolution"),
+        channel_locations,
         program,
-        texture,
-        pixel_ratio: 1,
+        channels,
     }
 }
 
-fn set_texture(gl: GL, texture_url: &'static str) -> Option<TextureInfo> {
-    let texture = gl.create_texture();
-    let res_texture = texture.clone();
-    let image = HtmlImageElement::new().expect_throw("failed to create Image element");
-    let cloned_image = image.clone();
-    let image_resolution = Arc::new([AtomicU32::new(0), AtomicU32::new(0)]);
-    let cloned_image_resolution = Arc::clone(&image_resolution);
-    gloo::events::EventListener::once(&image, "load", move |_| {
-        gl.bind_texture(GL::TEXTURE_2D, texture.as_ref());
-        gl.pixel_storei(GL::UNPACK_FLIP_Y_WEBGL, 1);
-        gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
-            GL::TEXTURE_2D,
-            0,
-            GL::RGBA as i32,
-            GL::RGBA,
-            GL::UNSIGNED_BYTE,
-            &cloned_image,
-        )
-        .unwrap_or_else(|e| panic!("{e:?}"));
-        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
-        gl.tex_parameteri(
-            GL::TEXTURE_2D,
-            GL::TEXTURE_MIN_FILTER,
-            GL::LINEAR_MIPMAP_NEAREST as i32,
-        );
-        gl.generate_mipmap(GL::TEXTURE_2D);
-        gl.bind_texture(GL::TEXTURE_2D, None);
-        cloned_image_resolution[0].store(cloned_image.natural_width(), Ordering::SeqCst);
-        cloned_image_resolution[1].store(cloned_image.natural_height(), Ordering::SeqCst);
-    })
-    .forget();
-    image.set_src(texture_url);
-    res_texture.map(|texture| TextureInfo {
-        texture,
-        resolution: image_resolution,
+/// Draws the image rendered at a lower resolution to the whole canvas,
+/// mixed with the image of the previous shader while they cross-fade.
+#[derive(Clone, Debug)]
+pub struct UpscalePipeline {
+    program: WebGlProgram,
+    position_location: u32,
+    image_location: Option<WebGlUniformLocation>,
+    previous_location: Option<WebGlUniformLocation>,
+    fade_location: Option<WebGlUniformLocation>,
+    resolution_location: Option<WebGlUniformLocation>,
+}
+
+/// Gets a WebGL2 context, or a WebGL1 context through the same bindings.
+/// Only the methods shared with WebGL1 are called for it, branching on `is_webgl1`.
+pub fn get_context(canvas: &HtmlCanvasElement) -> Option<GL> {
+    let context = |id| canvas.get_context(id).ok().flatten();
+    context("webgl2")
+        .or_else(|| context("webgl"))
+        .map(|context| context.unchecked_into())
+}
+
+/// `get_context` of the canvas transferred to the worker
+pub fn get_offscreen_context(canvas: &OffscreenCanvas) -> Option<GL> {
+    let context = |id| canvas.get_context(id).ok().flatten();
+    context("webgl2")
+        .or_else(|| context("webgl"))
+        .map(|context| context.unchecked_into())
+}
+
+pub fn is_webgl1(gl: &GL) -> bool {
+    !gl.is_instance_of::<GL>()
+}
+
+impl UpscalePipeline {
+    pub fn delete(&self, gl: &GL) {
+        gl.delete_program(Some(&self.program));
+    }
+}
+
+pub fn create_upscale_pipeline(gl: &GL) -> Result<UpscalePipeline, ShaderError> {
+    let (vertex_shader, fragment_shader) = match is_webgl1(gl) {
+        true => (VERTEX_SHADER_WEBGL1, UPSCALE_SHADER_WEBGL1),
+        false => (VERTEX_SHADER, UPSCALE_SHADER),
+    };
+    let program = link_program(gl, "upscale", vertex_shader, fragment_shader, "", &[])?;
+    Ok(UpscalePipeline {
+        position_location: gl.get_attrib_location(&program, "position") as u32,
+        image_location: gl.get_uniform_location(&program, "image"),
+        previous_location: gl.get_uniform_location(&program, "previous"),
+        fade_location: gl.get_uniform_location(&program, "fade"),
+        resolution_location: gl.get_uniform_location(&program, "resolution"),
+        program,
     })
 }
 
-fn prepare_program(gl: &GL, shader: ShaderSource) -> (WebGlProgram, Option<TextureInfo>) {
-    const VERTEX_SHADER: &str = "#version 300 es
+const VERTEX_SHADER: &str = "#version 300 es
 in vec3 position;void main(){gl_Position=vec4(position,1);}";
-    const FRAMENT_SHADER_PREFIX: &str = "#version 300 es
-precision highp float;uniform vec3 iResolution;uniform float iTime;uniform vec2 iMouse;\
-uniform sampler2D iChannel0;uniform vec3 iChannelResolution[1];\
-out vec4 outColor;void mainImage(out vec4,in vec2);void main(){mainImage(outColor,gl_FragCoord.xy);}";
+const UPSCALE_SHADER: &str = "#version 300 es
+precision highp float;uniform sampler2D image;uniform sampler2D previous;uniform float fade;uniform vec2 resolution;
+out vec4 outColor;void main(){vec2 uv=gl_FragCoord.xy/resolution;
+outColor=mix(texture(previous,uv),texture(image,uv),fade);}";
+// shared with `build.rs` to validate the shaders
+const FRAMENT_SHADER_PREFIX: &str = include_str!("prefix.frag");
+
+// GLSL ES 1.00 versions for WebGL1
+const VERTEX_SHADER_WEBGL1: &str =
+    "attribute vec3 position;void main(){gl_Position=vec4(position,1);}";
+const UPSCALE_SHADER_WEBGL1: &str =
+    "precision highp float;uniform sampler2D image;uniform sampler2D previous;uniform float fade;uniform vec2 resolution;
+void main(){vec2 uv=gl_FragCoord.xy/resolution;gl_FragColor=mix(texture2D(previous,uv),texture2D(image,uv),fade);}";
+/// `texture` is replaced by `texture2D`, so cubemaps and 3D textures are not supported
+const FRAMENT_SHADER_PREFIX_WEBGL1: &str = include_str!("prefix100.frag");
 
+fn prepare_program(gl: &GL, shader: ShaderSource<'_>) -> Result<WebGlProgram, ShaderError> {
+    let (vertex_shader, prefix) = match is_webgl1(gl) {
+        true => (VERTEX_SHADER_WEBGL1, FRAMENT_SHADER_PREFIX_WEBGL1),
+        false => (VERTEX_SHADER, FRAMENT_SHADER_PREFIX),
+    };
+    // the sampler types depend on the channels, declared without a newline to keep the line numbers
+    let samplers = shader.channels.iter().enumerate().map(|(idx, channel)| {
+        let ty = channel.map_or("sampler2D", |channel| channel.input.sampler_type());
+        format!("uniform {ty} iChannel{idx};")
+    });
+    let source = String::from(prefix) + &samplers.collect::<String>() + shader.source;
+    link_program(
+        gl,
+        shader.path,
+        vertex_shader,
+        &source,
+        prefix,
+        shader.source_lines,
+    )
+}
+
+/// Compiles and links the shaders, which are deleted once linked.
+/// The lines of the fragment shader after `prefix` are reported as `source_lines` of `path`.
+fn link_program(
+    gl: &GL,
+    path: &'static str,
+    vertex_shader: &str,
+    fragment_shader: &str,
+    prefix: &str,
+    source_lines: &[u32],
+) -> Result<WebGlProgram, ShaderError> {
     // vertex shader
-    let vert_shader = gl
-        .create_shader(GL::VERTEX_SHADER)
-        .expect_throw("failed to create shader pointer");
-    gl.shader_source(&vert_shader, VERTEX_SHADER);
-    gl.compile_shader(&vert_shader);
+    let vert_shader =
+        compile_shader(gl, GL::VERTEX_SHADER, vertex_shader).map_err(|log| ShaderError {
+            path,
+            stage: ShaderStage::Vertex,
+            log,
+            source_lines: Vec::new(),
+        })?;
 
     // fragment shader
-    let frag_shader = gl
-        .create_shader(GL::FRAGMENT_SHADER)
-        .expect_throw("failed to create shader pointer");
-    let texture = shader
-        .texture_url
-        .and_then(|texture_url| set_texture(gl.clone(), texture_url));
-    let shader = String::from(FRAMENT_SHADER_PREFIX) + shader.source;
-    gl.shader_source(&frag_shader, &shader);
-    gl.compile_shader(&frag_shader);
+    let frag_shader = compile_shader(gl, GL::FRAGMENT_SHADER, fragment_shader).map_err(|log| {
+        gl.delete_shader(Some(&vert_shader));
+        let (log, source_lines) = map_info_log(&log, prefix, source_lines);
+        ShaderError {
+            path,
+            stage: ShaderStage::Fragment,
+            log,
+            source_lines,
+        }
+    })?;
 
     // create program
     let program = gl
@@ -112,12 +271,87 @@ out vec4 outColor;void mainImage(out vec4,in vec2);void main(){mainImage(outColo
     gl.attach_shader(&program, &vert_shader);
     gl.attach_shader(&program, &frag_shader);
     gl.link_program(&program);
+    // the program keeps the linked binary
+    for shader in [vert_shader, frag_shader] {
+        gl.detach_shader(&program, &shader);
+        gl.delete_shader(Some(&shader));
+    }
+    if gl
+        .get_program_parameter(&program, GL::LINK_STATUS)
+        .as_bool()
+        != Some(true)
+    {
+        let log = gl.get_program_info_log(&program).unwrap_or_default();
+        gl.delete_program(Some(&program));
+        let (log, source_lines) = map_info_log(&log, prefix, source_lines);
+        return Err(ShaderError {
+            path,
+            stage: ShaderStage::Link,
+            log,
+            source_lines,
+        });
+    }
+    Ok(program)
+}
+
+fn compile_shader(gl: &GL, shader_type: u32, source: &str) -> Result<WebGlShader, String> {
+    let shader = gl
+        .create_shader(shader_type)
+        .expect_throw("failed to create shader pointer");
+    gl.shader_source(&shader, source);
+    gl.compile_shader(&shader);
+    match gl
+        .get_shader_parameter(&shader, GL::COMPILE_STATUS)
+        .as_bool()
+    {
+        Some(true) => Ok(shader),
+        _ => {
+            let log = gl.get_shader_info_log(&shader).unwrap_or_default();
+            gl.delete_shader(Some(&shader));
+            Err(log)
+        }
+    }
+}
 
-    (program, texture)
+/// Rewrites `ERROR: 0:<line>: ...` entries of the info log to the line numbers of the original `.frag`,
+/// skipping the lines of `prefix` and the comments stripped by `build.rs`.
+fn map_info_log(log: &str, prefix: &str, source_lines: &[u32]) -> (String, Vec<u32>) {
+    // `shader.source` starts on the last line of the prefix
+    let offset = prefix.matches('\n').count() + 1;
+    let mut lines = Vec::new();
+    let log = log
+        .lines()
+        .map(|line| {
+            let mut parts = line.splitn(4, ':');
+            let (kind, _, glsl_line, message) =
+                (parts.next(), parts.next(), parts.next(), parts.next());
+            let original = glsl_line
+                .and_then(|l| l.trim().parse::<usize>().ok())
+                .and_then(|l| l.checked_sub(offset))
+                .and_then(|idx| source_lines.get(idx))
+                .copied();
+            match (kind, original, message) {
+                (Some(kind), Some(original), Some(message)) => {
+                    if !lines.contains(&original) {
+                        lines.push(original);
+                    }
+                    format!("{kind}: line {original}:{message}")
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    (log, lines)
 }
 
 pub fn init_gl(gl: &GL) {
     gl.clear_color(0.0, 0.0, 0.0, 0.0);
+    if is_webgl1(gl) {
+        // `dFdx` and `texture2DLodEXT`, core features of GLSL ES 3.00
+        let _ = gl.get_extension("OES_standard_derivatives");
+        let _ = gl.get_extension("EXT_shader_texture_lod");
+    }
 
     #[rustfmt::skip]
     const POSITIONS: &[f32] = &[
@@ -127,7 +361,7 @@ pub fn init_gl(gl: &GL) {
          1.0, -1.0, 0.0,
     ];
     #[rustfmt::skip]
-    const INDEX: &[u32] = &[
+    const INDEX: &[u16] = &[
         0, 2, 1,
         1, 2, 3,
     ];
@@ -138,7 +372,8 @@ pub fn init_gl(gl: &GL) {
     gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &vertex_buffer_js, GL::STATIC_DRAW);
 
     let index_buffer = gl.create_buffer();
-    let index_buffer_js = js_sys::Uint32Array::from(INDEX);
+    // WebGL1 has no 32 bits indices without an extension
+    let index_buffer_js = js_sys::Uint16Array::from(INDEX);
     gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, index_buffer.as_ref());
     gl.buffer_data_with_array_buffer_view(
         GL::ELEMENT_ARRAY_BUFFER,
@@ -147,24 +382,170 @@ pub fn init_gl(gl: &GL) {
     );
 }
 
+/// Renders the graph at `uniforms.resolution` and upscales the image to the canvas if it is smaller.
+/// While the graph of the previous shader is `fading` out with its own uniforms by the weight of the new image,
+/// both images are rendered into their buffers and mixed.
 pub fn gl_rendering(
+    gl: &GL,
+    graph: &mut RenderGraph,
+    fading: Option<(&mut RenderGraph, &FrameUniforms, f32)>,
+    uniforms: &FrameUniforms,
+    canvas_size: [u32; 2],
+) {
+    let resolution = uniforms.resolution;
+    let render_size = [resolution[0] as i32, resolution[1] as i32];
+    let canvas_size = canvas_size.map(|size| size as i32);
+    let offscreen = render_size != canvas_size || fading.is_some();
+    render_passes(gl, graph, uniforms, offscreen);
+    let fading = fading.map(|(fading, fading_uniforms, fade)| {
+        render_passes(gl, fading, fading_uniforms, true);
+        (&*fading, fade)
+    });
+    gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+    if let Some(image) = graph.buffers.last().and_then(Option::as_ref) {
+        let previous = fading.and_then(|(fading, fade)| {
+            let previous = fading.buffers.last()?.as_ref()?;
+            Some((previous.front_texture(), fade))
+        });
+        gl.viewport(0, 0, canvas_size[0], canvas_size[1]);
+        draw_upscale(
+            gl,
+            &graph.upscale,
+            image.front_texture(),
+            previous,
+            canvas_size,
+        );
+    }
+    gl.flush();
+}
+
+/// Renders the passes in order, the image pass into its buffer if `offscreen`.
+fn render_passes(gl: &GL, graph: &mut RenderGraph, uniforms: &FrameUniforms, offscreen: bool) {
+    let resolution = uniforms.resolution;
+    let render_size = [resolution[0] as i32, resolution[1] as i32];
+    graph.prepare_buffers(gl, render_size, offscreen);
+    gl.viewport(0, 0, render_size[0], render_size[1]);
+    if let Some(keyboard) = &graph.keyboard {
+        keyboard.upload(gl);
+    }
+    graph
+        .passes
+        .iter()
+        .flat_map(|pass| pass.channels.iter().flatten())
+        .for_each(|binding| match &binding.channel {
+            Channel::Video(video) => video.upload(gl),
+            Channel::Audio(audio) => audio.upload(gl),
+            _ => {}
+        });
+    for idx in graph.order.clone() {
+        let pipeline = &graph.passes[idx];
+        let (buffers, keyboard) = (&graph.buffers, &graph.keyboard);
+        let textures =
+            std::array::from_fn(|unit| match &pipeline.channels[unit].as_ref()?.channel {
+                Channel::Texture(info) => Some((info.texture(), info.target(), info.resolution())),
+                Channel::Buffer(buffer) => buffers[*buffer].as_ref().map(|buffer| {
+                    let [width, height] = resolution;
+                    (buffer.front_texture(), GL::TEXTURE_2D, [width, height, 1.0])
+                }),
+                Channel::Keyboard => keyboard
+                    .as_ref()
+                    .map(|keyboard| (keyboard.texture(), GL::TEXTURE_2D, keyboard.resolution())),
+                Channel::Video(video) => {
+                    Some((video.texture(), GL::TEXTURE_2D, video.resolution()))
+                }
+                Channel::Audio(audio) => {
+                    Some((audio.texture(), GL::TEXTURE_2D, audio.resolution()))
+                }
+            });
+        let target = graph.buffers[idx]
+            .as_ref()
+            .map(FrameBuffer::back_framebuffer);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, target);
+        draw_pass(gl, pipeline, textures, uniforms);
+        if let Some(buffer) = &mut graph.buffers[idx] {
+            buffer.swap();
+        }
+    }
+}
+
+/// `previous` is the image of the previous shader and the weight of `image`.
+fn draw_upscale(
+    gl: &GL,
+    pipeline: &UpscalePipeline,
+    image: &WebGlTexture,
+    previous: Option<(&WebGlTexture, f32)>,
+    canvas_size: [i32; 2],
+) {
+    let UpscalePipeline {
+        program,
+        position_location,
+        image_location,
+        previous_location,
+        fade_location,
+        resolution_location,
+    } = pipeline;
+    gl.use_program(Some(program));
+    gl.enable_vertex_attrib_array(*position_location);
+    gl.vertex_attrib_pointer_with_i32(*position_location, 3, GL::FLOAT, false, 0, 0);
+    // the filters of the frame buffers interpolate the images
+    let (previous, fade) = previous.unwrap_or((image, 1.0));
+    for (unit, texture) in [image, previous].into_iter().enumerate() {
+        gl.active_texture(GL::TEXTURE0 + unit as u32);
+        gl.bind_texture(GL::TEXTURE_2D, Some(texture));
+        if !is_webgl1(gl) {
+            gl.bind_sampler(unit as u32, None);
+        }
+    }
+    gl.uniform1i(image_location.as_ref(), 0);
+    gl.uniform1i(previous_location.as_ref(), 1);
+    gl.uniform1f(fade_location.as_ref(), fade);
+    gl.uniform2f(
+        resolution_location.as_ref(),
+        canvas_size[0] as f32,
+        canvas_size[1] as f32,
+    );
+    gl.clear(GL::COLOR_BUFFER_BIT);
+    gl.draw_elements_with_i32(GL::TRIANGLES, 6, GL::UNSIGNED_SHORT, 0);
+}
+
+fn draw_pass(
     gl: &GL,
     pipeline: &Pipeline,
-    resolution: [f32; 2],
-    time: f32,
-    mouse_position: [f32; 2],
+    textures: [Option<(&WebGlTexture, u32, [f32; 3])>; CHANNEL_COUNT],
+    uniforms: &FrameUniforms,
 ) {
     let Pipeline {
         program,
+        channels,
         position_location,
         resolution_location,
         time_location,
+        time_delta_location,
+        frame_location,
+        frame_rate_location,
         mouse_location,
-        texture_location,
-        texture,
+        touch_location,
+        touch_count_location,
+        date_location,
+        sample_rate_location,
+        seed_location,
+        channel_time_location,
+        channel_locations,
         texture_resolution_location,
-        ..
     } = pipeline;
+    let FrameUniforms {
+        resolution,
+        time,
+        time_delta,
+        frame,
+        frame_rate,
+        mouse,
+        touches,
+        touch_count,
+        date,
+        sample_rate,
+        seed,
+    } = *uniforms;
     gl.use_program(Some(program));
 
     gl.enable_vertex_attrib_array(*position_location);
@@ -177,28 +558,64 @@ pub fn gl_rendering(
         1.0,
     );
     gl.uniform1f(time_location.as_ref(), time);
-    gl.uniform2f(
-        mouse_location.as_ref(),
-        mouse_position[0],
-        resolution[1] - mouse_position[1],
-    );
-    gl.uniform1i(texture_location.as_ref(), 0);
+    gl.uniform1f(time_delta_location.as_ref(), time_delta);
+    gl.uniform1i(frame_location.as_ref(), frame);
+    gl.uniform1f(frame_rate_location.as_ref(), frame_rate);
+    gl.uniform4fv_with_f32_array(mouse_location.as_ref(), &mouse);
+    gl.uniform4fv_with_f32_array(touch_location.as_ref(), &touches.concat());
+    gl.uniform1i(touch_count_location.as_ref(), touch_count);
+    gl.uniform4fv_with_f32_array(date_location.as_ref(), &date);
+    gl.uniform1f(sample_rate_location.as_ref(), sample_rate);
+    gl.uniform1f(seed_location.as_ref(), seed);
 
-    if let Some(TextureInfo {
-        texture,
-        resolution,
-    }) = texture
-    {
-        gl.bind_texture(GL::TEXTURE_2D, Some(texture));
-        gl.uniform3f(
-            texture_resolution_location.as_ref(),
-            resolution[0].load(Ordering::SeqCst) as f32,
-            resolution[1].load(Ordering::SeqCst) as f32,
-            1.0,
-        );
+    // every unit is rebound so that no texture of the current render target stays bound
+    let webgl1 = is_webgl1(gl);
+    let mut channel_resolutions = [0.0; CHANNEL_COUNT * 3];
+    let mut channel_times = [0.0; CHANNEL_COUNT];
+    for (unit, (binding, texture)) in channels.iter().zip(textures).enumerate() {
+        gl.active_texture(GL::TEXTURE0 + unit as u32);
+        let target = texture.map_or(GL::TEXTURE_2D, |(_, target, _)| target);
+        if target != GL::TEXTURE_2D {
+            gl.bind_texture(GL::TEXTURE_2D, None);
+        }
+        gl.bind_texture(target, texture.map(|(texture, ..)| texture));
+        match webgl1 {
+            // the settings belong to the texture without sampler objects
+            true => {
+                if let (Some(binding), Some((.., [width, height, _]))) = (binding, texture) {
+                    let size = [width as u32, height as u32];
+                    channel::set_texture_parameters(gl, target, binding.settings, size);
+                }
+            }
+            false => gl.bind_sampler(
+                unit as u32,
+                binding
+                    .as_ref()
+                    .and_then(|binding| binding.sampler.as_ref()),
+            ),
+        }
+        gl.uniform1i(channel_locations[unit].as_ref(), unit as i32);
+        if let (Some(binding), Some((.., channel_resolution))) = (binding, texture) {
+            match &binding.channel {
+                Channel::Buffer(_) => {
+                    // the buffers have the size of the canvas
+                    let size = [channel_resolution[0] as u32, channel_resolution[1] as u32];
+                    if binding.mipmap && channel::power_of_two(gl, size) {
+                        gl.generate_mipmap(GL::TEXTURE_2D);
+                    }
+                    channel_times[unit] = time;
+                }
+                Channel::Video(video) => channel_times[unit] = video.time(),
+                Channel::Audio(audio) => channel_times[unit] = audio.time(),
+                _ => {}
+            }
+            channel_resolutions[unit * 3..unit * 3 + 3].copy_from_slice(&channel_resolution);
+        }
     }
+    gl.active_texture(GL::TEXTURE0);
+    gl.uniform3fv_with_f32_array(texture_resolution_location.as_ref(), &channel_resolutions);
+    gl.uniform1fv_with_f32_array(channel_time_location.as_ref(), &channel_times);
 
     gl.clear(GL::COLOR_BUFFER_BIT);
-    gl.draw_elements_with_i32(GL::TRIANGLES, 6, GL::UNSIGNED_INT, 0);
-    gl.flush();
+    gl.draw_elements_with_i32(GL::TRIANGLES, 6, GL::UNSIGNED_SHORT, 0);
 }
@@ -0,0 +1,459 @@
+use super::channel::ChannelInput;
+use super::renderer::{Pointer, Renderer};
+use super::*;
+use gloo::events::EventListenerOptions;
+use wasm_bindgen::JsValue;
+
+/// The messages are `[kind, data]` arrays.
+/// The worker sends `ready` once it listens, since the messages before are lost,
+/// then `init` transfers the canvas with the shader, the fade and `document.baseURI`,
+/// `frame` posts a `FrameMessage` and `shader` changes the shader, whether to fade to it.
+/// The worker replies to every frame by `rendered` with whether the fade goes on,
+/// and sends `error` instead of the fallback of `BackGround`.
+const READY_MESSAGE: &str = "ready";
+const INIT_MESSAGE: &str = "init";
+const FRAME_MESSAGE: &str = "frame";
+const SHADER_MESSAGE: &str = "shader";
+const RENDERED_MESSAGE: &str = "rendered";
+const ERROR_MESSAGE: &str = "error";
+
+/// `document.baseURI` of the main thread from the init message,
+/// since the relative URLs would resolve against the blob URL of the worker script
+static BASE_URI: Mutex<Option<String>> = Mutex::new(None);
+
+/// The URL of an input resolved against the document in the worker.
+pub fn resolve_url(url: &str) -> String {
+    let base = BASE_URI.lock().unwrap();
+    let resolved = base
+        .as_deref()
+        .and_then(|base| Url::new_with_base(url, base).ok());
+    resolved.map_or_else(|| url.to_string(), |resolved| resolved.href())
+}
+
+/// Whether this wasm runs in the worker of `WorkerRenderer`, which calls `run_worker` instead of the app.
+pub fn in_worker() -> bool {
+    js_sys::global().is_instance_of::<DedicatedWorkerGlobalScope>()
+}
+
+/// whether the worker can bind the input, which has no DOM for the images, the media and the keys
+pub fn supports_input(input: ChannelInput) -> bool {
+    matches!(
+        input,
+        ChannelInput::Texture(_) | ChannelInput::VolumeBinary(_) | ChannelInput::Buffer(_)
+    )
+}
+
+/// The inputs of a frame rendered by the worker, posted by the render loop of `BackGround`.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameMessage {
+    pub timestamp: f64,
+    /// `iTime`
+    pub time: f64,
+    pub canvas_size: [u32; 2],
+    /// the width of the canvas in CSS pixels
+    pub client_width: i32,
+    pub mouse: [f32; 4],
+    pub touches: [[f32; 4]; TOUCH_COUNT],
+    pub touch_count: i32,
+    pub seed: u32,
+}
+
+/// the length of `FrameMessage` as a `Float64Array`
+const FRAME_LENGTH: usize = 11 + 4 * TOUCH_COUNT;
+
+impl FrameMessage {
+    fn to_js(&self) -> JsValue {
+        let [width, height] = self.canvas_size;
+        let mut values = vec![
+            self.timestamp,
+            self.time,
+            width as f64,
+            height as f64,
+            self.client_width as f64,
+        ];
+        values.extend(self.mouse.map(f64::from));
+        values.extend(self.touches.iter().flatten().map(|v| *v as f64));
+        values.extend([self.touch_count as f64, self.seed as f64]);
+        js_sys::Float64Array::from(&values[..]).into()
+    }
+
+    fn from_js(data: &JsValue) -> Option<FrameMessage> {
+        let values = data.dyn_ref::<js_sys::Float64Array>()?.to_vec();
+        if values.len() != FRAME_LENGTH {
+            return None;
+        }
+        let vec4 = |offset: usize| [0, 1, 2, 3].map(|i| values[offset + i] as f32);
+        let mut touches = [[0.0; 4]; TOUCH_COUNT];
+        touches
+            .iter_mut()
+            .enumerate()
+            .for_each(|(idx, touch)| *touch = vec4(9 + 4 * idx));
+        let tail = 9 + 4 * TOUCH_COUNT;
+        Some(FrameMessage {
+            timestamp: values[0],
+            time: values[1],
+            canvas_size: [values[2] as u32, values[3] as u32],
+            client_width: values[4] as i32,
+            mouse: vec4(5),
+            touches,
+            touch_count: values[tail] as i32,
+            seed: values[tail + 1] as u32,
+        })
+    }
+}
+
+/// The click of `mouse` was consumed when the frame was posted.
+impl Pointer for FrameMessage {
+    fn uniform(&self) -> [f32; 4] {
+        self.mouse
+    }
+
+    fn touch_uniform(&self) -> ([[f32; 4]; TOUCH_COUNT], i32) {
+        (self.touches, self.touch_count)
+    }
+}
+
+/// Renders the background in a worker running this wasm, on the frames posted by `BackGround`.
+/// A frame is posted only after the previous one is rendered, so the worker never falls behind.
+#[derive(Debug)]
+pub struct WorkerRenderer {
+    worker: Worker,
+    /// the object URL of the script starting the worker
+    script_url: String,
+    state: Arc<WorkerState>,
+    /// the size of the canvas in the worker
+    canvas_size: [u32; 2],
+    _handlers: Vec<EventListener>,
+}
+
+/// The replies of the worker.
+#[derive(Debug)]
+struct WorkerState {
+    /// the posted frame is not rendered yet, or the worker is not ready
+    busy: AtomicBool,
+    fading: AtomicBool,
+    /// the shader of the init message until the worker is ready
+    pending_shader: Mutex<Option<String>>,
+}
+
+impl WorkerRenderer {
+    /// Transfers the canvas to a new worker once it is ready, `None` without `OffscreenCanvas`,
+    /// without the preloads of trunk, or if a shader has an input that the worker cannot bind.
+    /// `on_error` gets the errors of the shaders and WebGL, and `on_failure` gets whether the canvas
+    /// was transferred if the worker does not start, e.g. without module workers.
+    pub fn spawn(
+        canvas: &HtmlCanvasElement,
+        shader_name: &str,
+        fade_duration: f64,
+        on_error: Callback<String>,
+        on_failure: Callback<bool>,
+    ) -> Option<WorkerRenderer> {
+        let offscreen = js_sys::Reflect::has(canvas, &"transferControlToOffscreen".into());
+        let supported = shaders::get_texts().values().all(|shader| {
+            let mut channels = shader
+                .passes
+                .iter()
+                .flat_map(|pass| pass.channels.iter().flatten());
+            channels.all(|channel| supports_input(channel.input))
+        });
+        if offscreen != Ok(true) || !supported {
+            return None;
+        }
+        let script_url = worker_script()?;
+        let options = WorkerOptions::new().type_(WorkerType::Module);
+        let worker = match Worker::new_with_options(&script_url, options) {
+            Ok(worker) => worker,
+            Err(_) => {
+                let _ = Url::revoke_object_url(&script_url);
+                return None;
+            }
+        };
+
+        let state = Arc::new(WorkerState {
+            busy: AtomicBool::new(true),
+            fading: AtomicBool::new(false),
+            pending_shader: Mutex::new(Some(shader_name.to_string())),
+        });
+        let (cloned_worker, cloned_canvas) = (worker.clone(), canvas.clone());
+        let base_uri = gloo::utils::document().base_uri().ok().flatten();
+        let base_uri = base_uri.unwrap_or_default();
+        let cloned_state = Arc::clone(&state);
+        let cloned_on_failure = on_failure.clone();
+        let on_message = move |e: &Event| {
+            let e = MessageEvent::from(JsValue::from(e.clone()));
+            match parse_message(&e.data()) {
+                Some((kind, _)) if kind == READY_MESSAGE => {
+                    let shader_name = match cloned_state.pending_shader.lock().unwrap().take() {
+                        Some(shader_name) => shader_name,
+                        None => return,
+                    };
+                    let offscreen = match cloned_canvas.transfer_control_to_offscreen() {
+                        Ok(offscreen) => offscreen,
+                        Err(_) => return cloned_on_failure.emit(false),
+                    };
+                    let data = js_sys::Array::of4(
+                        &offscreen,
+                        &shader_name.into(),
+                        &fade_duration.into(),
+                        &base_uri.as_str().into(),
+                    );
+                    cloned_worker
+                        .post_message_with_transfer(
+                            &message(INIT_MESSAGE, &data),
+                            &js_sys::Array::of1(&offscreen),
+                        )
+                        .expect_throw("failed to post message");
+                    cloned_state.busy.store(false, Ordering::SeqCst);
+                }
+                Some((kind, data)) if kind == RENDERED_MESSAGE => {
+                    let fading = data.as_bool().unwrap_or(false);
+                    cloned_state.fading.store(fading, Ordering::SeqCst);
+                    cloned_state.busy.store(false, Ordering::SeqCst);
+                }
+                Some((kind, data)) if kind == ERROR_MESSAGE => {
+                    on_error.emit(data.as_string().unwrap_or_default())
+                }
+                _ => {}
+            }
+        };
+        let cloned_state = Arc::clone(&state);
+        let on_worker_error = move |_: &Event| {
+            let transferred = cloned_state.pending_shader.lock().unwrap().is_none();
+            on_failure.emit(transferred);
+        };
+        Some(WorkerRenderer {
+            _handlers: vec![
+                EventListener::new(&worker, "message", on_message),
+                EventListener::new(&worker, "error", on_worker_error),
+            ],
+            worker,
+            script_url,
+            state,
+            canvas_size: [0, 0],
+        })
+    }
+
+    /// whether the previous frame is rendered
+    pub fn ready(&self) -> bool {
+        !self.state.busy.load(Ordering::SeqCst)
+    }
+
+    pub fn fading(&self) -> bool {
+        self.state.fading.load(Ordering::SeqCst)
+    }
+
+    pub fn resized(&self, canvas_size: [u32; 2]) -> bool {
+        self.canvas_size != canvas_size
+    }
+
+    pub fn post_frame(&mut self, frame: &FrameMessage) {
+        self.state.busy.store(true, Ordering::SeqCst);
+        self.canvas_size = frame.canvas_size;
+        self.worker
+            .post_message(&message(FRAME_MESSAGE, &frame.to_js()))
+            .expect_throw("failed to post message");
+    }
+
+    /// Changes the shader, fading from the previous one if `fade`.
+    pub fn post_shader(&self, shader_name: &str, fade: bool) {
+        if let Some(pending_shader) = &mut *self.state.pending_shader.lock().unwrap() {
+            *pending_shader = shader_name.to_string();
+            return;
+        }
+        let data = js_sys::Array::of2(&shader_name.into(), &fade.into());
+        self.worker
+            .post_message(&message(SHADER_MESSAGE, &data))
+            .expect_throw("failed to post message");
+    }
+}
+
+impl Drop for WorkerRenderer {
+    fn drop(&mut self) {
+        self.worker.terminate();
+        let _ = Url::revoke_object_url(&self.script_url);
+    }
+}
+
+/// A module script starting this wasm, from the preload links that trunk adds for the app.
+fn worker_script() -> Option<String> {
+    let document = gloo::utils::document();
+    let href = |selector: &str| {
+        let link = document.query_selector(selector).ok()??;
+        Some(link.dyn_into::<HtmlLinkElement>().ok()?.href())
+    };
+    let bindings = href("link[rel=modulepreload]")?;
+    let wasm = href("link[rel=preload][type='application/wasm']")?;
+    let script = format!("import init from \"{bindings}\";\ninit(\"{wasm}\");\n");
+    let parts = js_sys::Array::of1(&script.into());
+    let options = BlobPropertyBag::new().type_("text/javascript");
+    let blob = Blob::new_with_str_sequence_and_options(&parts, options).ok()?;
+    Url::create_object_url_with_blob(&blob).ok()
+}
+
+/// `[kind, data]`
+fn message(kind: &str, data: &JsValue) -> JsValue {
+    js_sys::Array::of2(&kind.into(), data).into()
+}
+
+fn parse_message(data: &JsValue) -> Option<(String, JsValue)> {
+    let message = data.dyn_ref::<js_sys::Array>()?;
+    Some((message.get(0).as_string()?, message.get(1)))
+}
+
+/// The transferred canvas and its renderer in the worker.
+struct WorkerScene {
+    canvas: OffscreenCanvas,
+    /// `None` while the context is lost or if WebGL failed
+    renderer: Option<Renderer>,
+    context_lost: bool,
+    shader_name: String,
+    fade_duration: f64,
+    _context_listeners: Vec<EventListener>,
+}
+
+type SharedScene = Arc<Mutex<Option<WorkerScene>>>;
+
+/// Renders the frames posted by `WorkerRenderer` to the transferred canvas, in place of the app.
+pub fn run_worker() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let scene: SharedScene = Default::default();
+    let on_message = move |e: &Event| {
+        let e = MessageEvent::from(JsValue::from(e.clone()));
+        let (kind, data) = match parse_message(&e.data()) {
+            Some(message) => message,
+            None => return,
+        };
+        let mut guard = scene.lock().unwrap();
+        let res = match (kind.as_str(), &mut *guard) {
+            // the scene stays after an error to render the next shader
+            (INIT_MESSAGE, _) => {
+                WorkerScene::new(&data, &scene).and_then(|new| guard.insert(new).init_renderer())
+            }
+            // every frame gets a reply, or `WorkerRenderer` waits for it forever
+            (FRAME_MESSAGE, current) => {
+                let frame = FrameMessage::from_js(&data);
+                let scene = current.as_mut().zip(frame);
+                let fading = scene.is_some_and(|(current, frame)| current.render(&frame));
+                post_to_main(RENDERED_MESSAGE, &fading.into());
+                Ok(())
+            }
+            (SHADER_MESSAGE, Some(current)) => {
+                let data = js_sys::Array::from(&data);
+                current.shader_name = data.get(0).as_string().unwrap_or_default();
+                match current.renderer.is_none() && !current.context_lost {
+                    // WebGL failed on the init
+                    true => current.init_renderer(),
+                    false => current.create_render_graph(data.get(1).as_bool().unwrap_or(false)),
+                }
+            }
+            // the init failed, and the error stays for the next shader
+            (SHADER_MESSAGE, None) => Err(String::from("no canvas in the init message")),
+            _ => Ok(()),
+        };
+        if let Err(e) = res {
+            gloo::console::error!(e.clone());
+            post_to_main(ERROR_MESSAGE, &e.into());
+        }
+    };
+    EventListener::new(&scope, "message", on_message).forget();
+    post_to_main(READY_MESSAGE, &JsValue::UNDEFINED);
+}
+
+fn post_to_main(kind: &str, data: &JsValue) {
+    js_sys::global()
+        .unchecked_into::<DedicatedWorkerGlobalScope>()
+        .post_message(&message(kind, data))
+        .expect_throw("failed to post message");
+}
+
+impl WorkerScene {
+    fn new(data: &JsValue, scene: &SharedScene) -> Result<WorkerScene, String> {
+        let data = js_sys::Array::from(data);
+        let canvas: OffscreenCanvas = data
+            .get(0)
+            .dyn_into()
+            .map_err(|_| String::from("no canvas in the init message"))?;
+        *BASE_URI.lock().unwrap() = data.get(3).as_string();
+        Ok(WorkerScene {
+            _context_listeners: listen_context_loss(&canvas, scene),
+            canvas,
+            renderer: None,
+            context_lost: false,
+            shader_name: data.get(1).as_string().unwrap_or_default(),
+            fade_duration: data.get(2).as_f64().unwrap_or(FADE_DURATION),
+        })
+    }
+
+    /// on the init and after a context loss
+    fn init_renderer(&mut self) -> Result<(), String> {
+        let gl = webgl::get_offscreen_context(&self.canvas)
+            .ok_or_else(|| String::from("Failed to init WebGL..."))?;
+        self.renderer = Some(Renderer::new(gl, self.fade_duration));
+        self.create_render_graph(false)
+    }
+
+    fn create_render_graph(&mut self, fade: bool) -> Result<(), String> {
+        let renderer = match &mut self.renderer {
+            Some(renderer) => renderer,
+            // a lost context creates it on the restore
+            None => return Ok(()),
+        };
+        let shader =
+            get_shader(&self.shader_name).ok_or_else(|| String::from("failed to load shader"))?;
+        let render_graph =
+            render_graph::create_render_graph(&renderer.gl, shader).map_err(|e| e.to_string())?;
+        renderer.set_render_graph(render_graph, fade);
+        Ok(())
+    }
+
+    /// Renders the frame, whether the fade goes on.
+    fn render(&mut self, frame: &FrameMessage) -> bool {
+        let renderer = match &mut self.renderer {
+            Some(renderer) => renderer,
+            None => return false,
+        };
+        let [width, height] = frame.canvas_size;
+        // resizing clears the canvas
+        if self.canvas.width() != width || self.canvas.height() != height {
+            self.canvas.set_width(width);
+            self.canvas.set_height(height);
+        }
+        renderer.render(
+            frame.timestamp,
+            frame.time,
+            frame.canvas_size,
+            frame.client_width,
+            frame,
+            frame.seed,
+        );
+        renderer.fading()
+    }
+}
+
+/// The worker version of `listen_context_loss` of `BackGround`.
+fn listen_context_loss(canvas: &OffscreenCanvas, scene: &SharedScene) -> Vec<EventListener> {
+    let options = EventListenerOptions::enable_prevent_default();
+    let cloned_scene = Arc::clone(scene);
+    let on_lost = move |e: &Event| {
+        e.prevent_default();
+        if let Some(scene) = &mut *cloned_scene.lock().unwrap() {
+            scene.context_lost = true;
+            if let Some(renderer) = scene.renderer.take() {
+                renderer.stop_media();
+            }
+        }
+    };
+    let cloned_scene = Arc::clone(scene);
+    let on_restored = move |_: &Event| {
+        if let Some(scene) = &mut *cloned_scene.lock().unwrap() {
+            scene.context_lost = false;
+            if let Err(e) = scene.init_renderer() {
+                post_to_main(ERROR_MESSAGE, &e.into());
+            }
+        }
+    };
+    vec![
+        EventListener::new_with_options(canvas, "webglcontextlost", options, on_lost),
+        EventListener::new(canvas, "webglcontextrestored", on_restored),
+    ]
+}
@@ -0,0 +1,583 @@
+use super::texture::{Sampler, Texture, TextureKind};
+use super::value::{Pointer, Result, Root, Value, Vector};
+use naga::{
+    AddressSpace, Binding, BuiltIn, Expression, Function, Handle, Literal, Module, SampleLevel,
+    Statement, SwitchValue, TypeInner,
+};
+use std::collections::HashMap;
+
+/// A fragment shader in naga's IR evaluated on the CPU.
+///
+/// Derivatives are zero since each pixel runs alone, so `fwidth` never anti-aliases
+/// and textures are sampled at the base level.
+pub struct Program {
+    module: Module,
+    /// the values of `module.const_expressions`
+    constants: Vec<Value>,
+}
+
+/// the texture and the sampler bound to each `iChannelN`
+pub type Channels<'a> = [Option<(&'a Texture, Sampler)>; 4];
+
+/// the state of a pixel
+struct Invocation<'a> {
+    program: &'a Program,
+    channels: &'a Channels<'a>,
+    globals: Vec<Value>,
+    frames: Vec<Frame>,
+    /// the number of the frames in use, the rest are kept for the next calls
+    depth: usize,
+}
+
+#[derive(Default)]
+struct Frame {
+    arguments: Vec<Value>,
+    locals: Vec<Value>,
+    /// the evaluated expressions, emitted ones are evaluated again by each `Emit`
+    expressions: Vec<Option<Value>>,
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Option<Value>),
+    Kill,
+}
+
+impl Program {
+    pub fn new(module: Module) -> Result<Self> {
+        let mut program = Self {
+            module,
+            constants: Vec::new(),
+        };
+        // constant expressions only refer to the preceding ones
+        let mut constants = Vec::new();
+        for (_, expression) in program.module.const_expressions.iter() {
+            let value = program.constant(expression, &constants)?;
+            constants.push(value);
+        }
+        program.constants = constants;
+        program
+            .module
+            .entry_points
+            .iter()
+            .find(|entry| entry.name == "main")
+            .ok_or("no main function")?;
+        Ok(program)
+    }
+
+    fn constant(&self, expression: &Expression, constants: &[Value]) -> Result<Value> {
+        let get = |handle: &Handle<Expression>| {
+            constants
+                .get(handle.index())
+                .cloned()
+                .ok_or_else(|| String::from("constant expression out of order"))
+        };
+        Ok(match expression {
+            Expression::Literal(literal) => literal_value(literal)?,
+            Expression::Constant(constant) => get(&self.module.constants[*constant].init)?,
+            Expression::ZeroValue(ty) => self.zero_value(*ty)?,
+            Expression::Compose { ty, components } => {
+                let components = components.iter().map(get).collect::<Result<Vec<_>>>()?;
+                self.compose(*ty, components)?
+            }
+            Expression::Splat { size, value } => get(value)?.splat(*size as usize)?,
+            expression => return Err(format!("unsupported constant expression {expression:?}")),
+        })
+    }
+
+    fn zero_value(&self, ty: Handle<naga::Type>) -> Result<Value> {
+        Ok(match &self.module.types[ty].inner {
+            TypeInner::Scalar(scalar) => Value::zero(scalar.kind, 1)?,
+            TypeInner::Vector { size, scalar } => Value::zero(scalar.kind, *size as usize)?,
+            TypeInner::Matrix { columns, rows, .. } => {
+                Value::compose_matrix(*columns as usize, *rows as usize, &[])?
+            }
+            TypeInner::Array {
+                base,
+                size: naga::ArraySize::Constant(size),
+                ..
+            } => Value::Composite(vec![self.zero_value(*base)?; size.get() as usize]),
+            TypeInner::Struct { members, .. } => Value::Composite(
+                members
+                    .iter()
+                    .map(|member| self.zero_value(member.ty))
+                    .collect::<Result<_>>()?,
+            ),
+            inner => return Err(format!("unsupported type {inner:?}")),
+        })
+    }
+
+    fn compose(&self, ty: Handle<naga::Type>, components: Vec<Value>) -> Result<Value> {
+        match &self.module.types[ty].inner {
+            TypeInner::Scalar(scalar) => Value::compose_vector(scalar.kind, &components),
+            TypeInner::Vector { scalar, .. } => Value::compose_vector(scalar.kind, &components),
+            TypeInner::Matrix { columns, rows, .. } => {
+                Value::compose_matrix(*columns as usize, *rows as usize, &components)
+            }
+            _ => Ok(Value::Composite(components)),
+        }
+    }
+
+    /// Runs `main` for the pixel at `position` and returns the color written to `outColor`.
+    /// `uniforms` are the members of the uniform block by their names, missing ones are zero.
+    pub fn run(
+        &self,
+        position: [f32; 2],
+        uniforms: &HashMap<&str, Value>,
+        channels: &Channels,
+    ) -> Result<[f32; 4]> {
+        let globals = self
+            .module
+            .global_variables
+            .iter()
+            .map(|(_, global)| self.global_value(global, uniforms))
+            .collect::<Result<Vec<_>>>()?;
+        let mut invocation = Invocation {
+            program: self,
+            channels,
+            globals,
+            frames: Vec::new(),
+            depth: 0,
+        };
+        let entry = self
+            .module
+            .entry_points
+            .iter()
+            .find(|entry| entry.name == "main")
+            .ok_or("no main function")?;
+        let arguments = entry
+            .function
+            .arguments
+            .iter()
+            .map(|argument| match argument.binding {
+                Some(Binding::BuiltIn(BuiltIn::Position { .. })) => {
+                    Ok(Value::float(&[position[0], position[1], 0.5, 1.0]))
+                }
+                Some(Binding::BuiltIn(BuiltIn::FrontFacing)) => {
+                    Ok(Value::Bool(Vector::scalar(true)))
+                }
+                _ => self.zero_value(argument.ty),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let output = match invocation.call(&entry.function, arguments)? {
+            Some(output) => output,
+            // discarded
+            None => return Ok([0.0; 4]),
+        };
+        let result = entry.function.result.as_ref().ok_or("main has no output")?;
+        let color = match &self.module.types[result.ty].inner {
+            TypeInner::Struct { members, .. } => {
+                let location = members
+                    .iter()
+                    .position(|member| {
+                        matches!(member.binding, Some(Binding::Location { location: 0, .. }))
+                    })
+                    .ok_or("no output at the location 0")?;
+                output.index(location as u32)?
+            }
+            _ => output,
+        };
+        let color = color.as_floats()?;
+        Ok([0, 1, 2, 3].map(|idx| color.data[idx]))
+    }
+
+    fn global_value(
+        &self,
+        global: &naga::GlobalVariable,
+        uniforms: &HashMap<&str, Value>,
+    ) -> Result<Value> {
+        let name = global.name.as_deref().unwrap_or_default();
+        let channel = |suffix: &str| {
+            name.strip_prefix("iChannel")
+                .and_then(|name| name.strip_suffix(suffix))
+                .and_then(|idx| idx.parse::<usize>().ok())
+        };
+        Ok(match global.space {
+            AddressSpace::Handle => match (channel("_texture"), channel("_sampler")) {
+                (Some(idx), _) => Value::Image(idx),
+                (_, Some(idx)) => Value::Sampler(idx),
+                _ => return Err(format!("unknown resource {name}")),
+            },
+            AddressSpace::Uniform => match &self.module.types[global.ty].inner {
+                TypeInner::Struct { members, .. } => Value::Composite(
+                    members
+                        .iter()
+                        .map(|member| {
+                            let name = member.name.as_deref().unwrap_or_default();
+                            match uniforms.get(name) {
+                                Some(value) => Ok(value.clone()),
+                                None => self.zero_value(member.ty),
+                            }
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+                _ => return Err(format!("unknown uniform {name}")),
+            },
+            _ => match global.init {
+                Some(init) => self.constants[init.index()].clone(),
+                None => self.zero_value(global.ty)?,
+            },
+        })
+    }
+}
+
+impl<'a> Invocation<'a> {
+    fn call(&mut self, function: &Function, arguments: Vec<Value>) -> Result<Option<Value>> {
+        if self.depth == self.frames.len() {
+            self.frames.push(Frame::default());
+        }
+        let frame = &mut self.frames[self.depth];
+        frame.arguments = arguments;
+        frame.expressions.clear();
+        frame.expressions.resize(function.expressions.len(), None);
+        frame.locals.clear();
+        self.depth += 1;
+        let res = self.call_frame(function);
+        self.depth -= 1;
+        res
+    }
+
+    fn call_frame(&mut self, function: &Function) -> Result<Option<Value>> {
+        for (_, local) in function.local_variables.iter() {
+            let value = match local.init {
+                Some(init) => self.eval(function, init)?,
+                None => self.program.zero_value(local.ty)?,
+            };
+            self.frame().locals.push(value);
+        }
+        Ok(match self.block(function, &function.body)? {
+            Flow::Return(value) => value,
+            Flow::Kill => None,
+            _ => None,
+        })
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        &mut self.frames[self.depth - 1]
+    }
+
+    fn block(&mut self, function: &Function, block: &naga::Block) -> Result<Flow> {
+        for statement in block.iter() {
+            match self.statement(function, statement)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, function: &Function, statement: &Statement) -> Result<Flow> {
+        match statement {
+            Statement::Emit(range) => {
+                for handle in range.clone() {
+                    let value = self.expression(function, handle)?;
+                    self.frame().expressions[handle.index()] = Some(value);
+                }
+            }
+            Statement::Block(block) => return self.block(function, block),
+            Statement::If {
+                condition,
+                accept,
+                reject,
+            } => {
+                return match self.eval(function, *condition)?.as_bool()? {
+                    true => self.block(function, accept),
+                    false => self.block(function, reject),
+                }
+            }
+            Statement::Switch { selector, cases } => {
+                let selector = self.eval(function, *selector)?;
+                let selector = match selector {
+                    Value::Int(v) => SwitchValue::I32(v.data[0]),
+                    Value::Uint(v) => SwitchValue::U32(v.data[0]),
+                    _ => return Err(format!("invalid selector {selector:?}")),
+                };
+                let start = cases
+                    .iter()
+                    .position(|case| case.value == selector)
+                    .or_else(|| {
+                        cases
+                            .iter()
+                            .position(|case| case.value == SwitchValue::Default)
+                    });
+                if let Some(start) = start {
+                    for case in &cases[start..] {
+                        match self.block(function, &case.body)? {
+                            Flow::Break => break,
+                            Flow::Next if case.fall_through => {}
+                            Flow::Next => break,
+                            flow => return Ok(flow),
+                        }
+                    }
+                }
+            }
+            Statement::Loop {
+                body,
+                continuing,
+                break_if,
+            } => loop {
+                match self.block(function, body)? {
+                    Flow::Break => break,
+                    Flow::Next | Flow::Continue => {}
+                    flow => return Ok(flow),
+                }
+                match self.block(function, continuing)? {
+                    Flow::Next => {}
+                    flow => return Ok(flow),
+                }
+                if let Some(break_if) = break_if {
+                    if self.eval(function, *break_if)?.as_bool()? {
+                        break;
+                    }
+                }
+            },
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Return { value } => {
+                let value = value.map(|value| self.eval(function, value)).transpose()?;
+                return Ok(Flow::Return(value));
+            }
+            Statement::Kill => return Ok(Flow::Kill),
+            Statement::Store { pointer, value } => {
+                let pointer = self.eval(function, *pointer)?;
+                let value = self.eval(function, *value)?;
+                self.store(pointer, value)?;
+            }
+            Statement::Call {
+                function: callee,
+                arguments,
+                result,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.eval(function, *argument))
+                    .collect::<Result<Vec<_>>>()?;
+                let program = self.program;
+                let value = self.call(&program.module.functions[*callee], arguments)?;
+                if let Some(result) = result {
+                    self.frame().expressions[result.index()] = value;
+                }
+            }
+            statement => return Err(format!("unsupported statement {statement:?}")),
+        }
+        Ok(Flow::Next)
+    }
+
+    /// the value of an emitted expression, or of an expression which needs no emission
+    fn eval(&mut self, function: &Function, handle: Handle<Expression>) -> Result<Value> {
+        if let Some(value) = &self.frame().expressions[handle.index()] {
+            return Ok(value.clone());
+        }
+        let value = self.expression(function, handle)?;
+        self.frame().expressions[handle.index()] = Some(value.clone());
+        Ok(value)
+    }
+
+    fn expression(&mut self, function: &Function, handle: Handle<Expression>) -> Result<Value> {
+        let program = self.program;
+        Ok(match &function.expressions[handle] {
+            Expression::Literal(literal) => literal_value(literal)?,
+            Expression::Constant(constant) => {
+                program.constants[program.module.constants[*constant].init.index()].clone()
+            }
+            Expression::ZeroValue(ty) => program.zero_value(*ty)?,
+            Expression::Compose { ty, components } => {
+                let components = components
+                    .iter()
+                    .map(|component| self.eval(function, *component))
+                    .collect::<Result<Vec<_>>>()?;
+                program.compose(*ty, components)?
+            }
+            Expression::Access { base, index } => {
+                let index = self.eval(function, *index)?.as_index()?;
+                self.access(function, *base, index)?
+            }
+            Expression::AccessIndex { base, index } => self.access(function, *base, *index)?,
+            Expression::Splat { size, value } => {
+                self.eval(function, *value)?.splat(*size as usize)?
+            }
+            Expression::Swizzle {
+                size,
+                vector,
+                pattern,
+            } => self
+                .eval(function, *vector)?
+                .swizzle(&pattern[..*size as usize])?,
+            Expression::FunctionArgument(idx) => self.frame().arguments[*idx as usize].clone(),
+            Expression::GlobalVariable(global) => {
+                match program.module.global_variables[*global].space {
+                    AddressSpace::Handle => self.globals[global.index()].clone(),
+                    _ => Value::Pointer(Pointer::new(Root::Global(global.index()))),
+                }
+            }
+            Expression::LocalVariable(local) => Value::Pointer(Pointer::new(Root::Local {
+                frame: self.depth - 1,
+                index: local.index(),
+            })),
+            Expression::Load { pointer } => match self.eval(function, *pointer)? {
+                Value::Pointer(pointer) => self.load(&pointer)?,
+                value => return Err(format!("cannot load {value:?}")),
+            },
+            Expression::ArrayLength(array) => {
+                let array = match self.eval(function, *array)? {
+                    Value::Pointer(pointer) => self.load(&pointer)?,
+                    value => value,
+                };
+                match array {
+                    Value::Composite(elements) => {
+                        Value::Uint(Vector::scalar(elements.len() as u32))
+                    }
+                    value => return Err(format!("no length of {value:?}")),
+                }
+            }
+            Expression::ImageSample {
+                image,
+                coordinate,
+                offset,
+                level,
+                gather: None,
+                depth_ref: None,
+                ..
+            } => {
+                let (texture, sampler) = self.channel(function, *image)?;
+                let mut coordinate = self.eval(function, *coordinate)?.as_floats()?;
+                if let (Some(offset), TextureKind::Flat) = (offset, texture.kind) {
+                    let offset = program.constants[offset.index()].as_ints()?;
+                    (0..2).for_each(|idx| {
+                        coordinate.data[idx] += offset.data[idx] as f32 / texture.size[idx] as f32
+                    });
+                }
+                // the level is evaluated for its side effects only, there are no mipmaps
+                if let SampleLevel::Exact(level) | SampleLevel::Bias(level) = level {
+                    self.eval(function, *level)?;
+                }
+                Value::float(&texture.sample(sampler, coordinate.as_slice()))
+            }
+            Expression::ImageLoad {
+                image, coordinate, ..
+            } => {
+                let (texture, _) = self.channel(function, *image)?;
+                let coordinate = self.eval(function, *coordinate)?.as_ints()?;
+                let coordinate = [0, 1, 2].map(|idx| match idx < coordinate.len {
+                    true => coordinate.data[idx],
+                    false => 0,
+                });
+                Value::float(&texture.fetch(coordinate))
+            }
+            Expression::ImageQuery { image, query } => {
+                let (texture, _) = self.channel(function, *image)?;
+                match query {
+                    naga::ImageQuery::Size { .. } => {
+                        let size = texture.size.map(|v| v as u32);
+                        match texture.kind {
+                            TextureKind::Volume => Value::Uint(Vector::new(&size)),
+                            _ => Value::Uint(Vector::new(&size[..2])),
+                        }
+                    }
+                    naga::ImageQuery::NumLevels => Value::Uint(Vector::scalar(1)),
+                    query => return Err(format!("unsupported image query {query:?}")),
+                }
+            }
+            Expression::Unary { op, expr } => Value::unary(*op, self.eval(function, *expr)?)?,
+            Expression::Binary { op, left, right } => {
+                let left = self.eval(function, *left)?;
+                let right = self.eval(function, *right)?;
+                Value::binary(*op, left, right)?
+            }
+            Expression::Select {
+                condition,
+                accept,
+                reject,
+            } => {
+                let condition = self.eval(function, *condition)?;
+                let accept = self.eval(function, *accept)?;
+                let reject = self.eval(function, *reject)?;
+                Value::select(condition, accept, reject)?
+            }
+            Expression::Derivative { expr, .. } => match self.eval(function, *expr)? {
+                Value::Float(v) => Value::Float(v.map(|_| 0.0)),
+                value => return Err(format!("cannot differentiate {value:?}")),
+            },
+            Expression::Relational { fun, argument } => {
+                Value::relational(*fun, self.eval(function, *argument)?)?
+            }
+            Expression::Math {
+                fun,
+                arg,
+                arg1,
+                arg2,
+                arg3,
+            } => {
+                let args = std::iter::once(arg)
+                    .chain([arg1, arg2, arg3].into_iter().flatten())
+                    .map(|arg| self.eval(function, *arg))
+                    .collect::<Result<Vec<_>>>()?;
+                Value::math(*fun, &args)?
+            }
+            Expression::As {
+                expr,
+                kind,
+                convert,
+            } => self.eval(function, *expr)?.cast(*kind, convert.is_some())?,
+            Expression::CallResult(_) => return Err(String::from("call result before the call")),
+            expression => return Err(format!("unsupported expression {expression:?}")),
+        })
+    }
+
+    /// a pointer into the variable if `base` is a pointer, otherwise the component
+    fn access(
+        &mut self,
+        function: &Function,
+        base: Handle<Expression>,
+        index: u32,
+    ) -> Result<Value> {
+        Ok(match self.eval(function, base)? {
+            Value::Pointer(pointer) => Value::Pointer(pointer.access(index)?),
+            value => value.index(index)?,
+        })
+    }
+
+    fn channel(
+        &mut self,
+        function: &Function,
+        image: Handle<Expression>,
+    ) -> Result<(&'a Texture, Sampler)> {
+        match self.eval(function, image)? {
+            Value::Image(idx) => {
+                self.channels[idx].ok_or_else(|| format!("iChannel{idx} has no input"))
+            }
+            value => Err(format!("{value:?} is not a texture")),
+        }
+    }
+
+    fn variable(&mut self, root: Root) -> &mut Value {
+        match root {
+            Root::Local { frame, index } => &mut self.frames[frame].locals[index],
+            Root::Global(index) => &mut self.globals[index],
+        }
+    }
+
+    fn load(&mut self, pointer: &Pointer) -> Result<Value> {
+        self.variable(pointer.root).get(pointer.path())
+    }
+
+    fn store(&mut self, pointer: Value, value: Value) -> Result<()> {
+        match pointer {
+            Value::Pointer(pointer) => self.variable(pointer.root).set(pointer.path(), value),
+            pointer => Err(format!("cannot store into {pointer:?}")),
+        }
+    }
+}
+
+fn literal_value(literal: &Literal) -> Result<Value> {
+    Ok(match *literal {
+        Literal::F64(v) | Literal::AbstractFloat(v) => Value::float(&[v as f32]),
+        Literal::F32(v) => Value::float(&[v]),
+        Literal::U32(v) => Value::Uint(Vector::scalar(v)),
+        Literal::I32(v) => Value::Int(Vector::scalar(v)),
+        Literal::I64(v) | Literal::AbstractInt(v) => Value::Int(Vector::scalar(v as i32)),
+        Literal::Bool(v) => Value::Bool(Vector::scalar(v)),
+    })
+}
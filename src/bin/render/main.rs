@@ -0,0 +1,472 @@
+//! Renders a background shader on the CPU, e.g. for OG images and golden-image tests without a GPU.
+//!
+//! ```sh
+//! cargo run --release --bin render -- default --size 1200x630 --time 10 --out caption.png
+//! cargo run --release --bin render -- default --duration 2 --fps 15 --out default.png  # an animated PNG
+//! cargo run --release --bin render -- default --duration 2 --out frames  # a directory of PNG frames
+//! cargo run --release --bin render -- default --mouse 320,180 --textures tests/golden/textures
+//! cargo run --release --bin render -- --posters dist/posters --size 256x144  # every shader
+//! ```
+mod interpreter;
+#[allow(dead_code)]
+#[path = "../../../build/shaders.rs"]
+mod shaders;
+mod texture;
+mod value;
+
+use interpreter::{Channels, Program};
+use shaders::{ChannelInput, Filter, ShaderSource, ShaderValidator, Wrap};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use texture::{Sampler, Texture};
+use value::{Value, Vector};
+
+const USAGE: &str = "usage: render <shader> [--size <width>x<height>] [--time <seconds>] \
+[--duration <seconds>] [--fps <frames per second>] [--mouse <x>,<y>] [--seed <iSeed>] \
+[--textures <directory>] [--out <file.png or directory>]
+       render --posters <directory> [the options above but --out]";
+
+#[derive(Clone)]
+struct Options {
+    shader: String,
+    size: [usize; 2],
+    time: f32,
+    duration: f32,
+    fps: f32,
+    /// `iMouse.xy`, the cursor hovers without pressing
+    mouse: [f32; 2],
+    seed: f32,
+    /// the directories searched for the textures
+    textures: Vec<PathBuf>,
+    out: PathBuf,
+}
+
+/// a pass of the render graph and the textures of its channels, `None` for the buffers
+struct Pass {
+    program: Program,
+    shader: ShaderSource,
+    textures: [Option<Texture>; 4],
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    let res = match args.peek().map(String::as_str) {
+        Some("--posters") => render_posters(args.skip(1)),
+        _ => parse_options(args).and_then(|options| render(&options)),
+    };
+    if let Err(e) = res {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let shader = args.next().ok_or(USAGE)?;
+    let mut options = Options {
+        out: PathBuf::from(format!("{shader}.png")),
+        shader,
+        size: [640, 360],
+        time: 0.0,
+        duration: 0.0,
+        fps: 30.0,
+        mouse: [0.0; 2],
+        seed: 0.0,
+        textures: texture::TEXTURE_DIRS
+            .iter()
+            .map(|dir| Path::new(env!("CARGO_MANIFEST_DIR")).join(dir))
+            .collect(),
+    };
+    while let Some(key) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{key} needs a value\n{USAGE}"))?;
+        let number = |value: &str| {
+            value
+                .parse::<f32>()
+                .map_err(|_| format!("{key} must be a number"))
+        };
+        match key.as_str() {
+            "--size" => {
+                let size = value
+                    .split_once('x')
+                    .and_then(|(width, height)| Some([width.parse().ok()?, height.parse().ok()?]));
+                options.size = size.ok_or("--size must be <width>x<height>")?;
+            }
+            "--time" => options.time = number(&value)?,
+            "--duration" => options.duration = number(&value)?,
+            "--fps" => options.fps = number(&value)?,
+            "--mouse" => {
+                let mouse = value
+                    .split_once(',')
+                    .and_then(|(x, y)| Some([x.parse().ok()?, y.parse().ok()?]));
+                options.mouse = mouse.ok_or("--mouse must be <x>,<y>")?;
+            }
+            "--seed" => options.seed = number(&value)?,
+            "--textures" => options.textures = vec![PathBuf::from(value)],
+            "--out" => options.out = PathBuf::from(value),
+            _ => return Err(format!("unknown option {key}\n{USAGE}")),
+        }
+    }
+    Ok(options)
+}
+
+/// Renders every shader into `<directory>/<shader>.png`, the posters shown without WebGL,
+/// each on a thread.
+fn render_posters(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let dir = PathBuf::from(args.next().ok_or(USAGE)?);
+    let options = parse_options(std::iter::once(String::new()).chain(args))?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+    let names = shader_names(&root).map_err(|e| e.to_string())?;
+    std::thread::scope(|scope| {
+        let handles = names
+            .into_iter()
+            .map(|name| {
+                let options = Options {
+                    out: dir.join(format!("{name}.png")),
+                    shader: name,
+                    ..options.clone()
+                };
+                scope.spawn(move || render(&options))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("a render panicked"))
+    })
+}
+
+/// the names of the shaders, in subdirectories too
+fn shader_names(dir: &Path) -> std::io::Result<Vec<String>> {
+    std::fs::read_dir(dir)?.try_fold(Vec::new(), |mut names, entry| {
+        let path = entry?.path();
+        match shaders::render_graph_passes(&path) {
+            Some(_) => names.push(path.file_stem().unwrap().to_str().unwrap().to_string()),
+            None => names.extend(shader_names(&path)?),
+        }
+        Ok(names)
+    })
+}
+
+fn render(options: &Options) -> Result<(), String> {
+    let passes = load_passes(options)?;
+    let image = passes.len() - 1;
+    let order = sort_passes(&passes);
+    let [width, height] = options.size;
+    let frame_count = (options.duration * options.fps).round() as usize + 1;
+    // the buffers start cleared like the frame buffers of WebGL
+    let mut buffers = passes
+        .iter()
+        .map(|_| Texture {
+            texels: vec![[0.0; 4]; width * height],
+            ..Texture::empty(width, height)
+        })
+        .collect::<Vec<_>>();
+    let mut frames = Vec::new();
+    for frame in 0..frame_count {
+        let time = options.time + frame as f32 / options.fps;
+        for idx in &order {
+            let pass = &passes[*idx];
+            let mut uniforms = frame_uniforms(options, frame, time);
+            let texels = {
+                let channels = channels(pass, &buffers);
+                let resolutions = channels
+                    .iter()
+                    .map(|channel| channel.map_or([0.0; 3], |(texture, _)| texture.resolution()));
+                uniforms.insert(
+                    "iChannelResolution",
+                    Value::Composite(resolutions.map(|v| Value::float(&v)).collect()),
+                );
+                render_pass(pass, &uniforms, &channels, options.size)?
+            };
+            // the following passes read this frame
+            buffers[*idx].texels = texels;
+        }
+        eprintln!(
+            "{}: rendered frame {} / {frame_count}",
+            options.shader,
+            frame + 1
+        );
+        frames.push(to_rgba8(&buffers[image]));
+    }
+    write_frames(options, &frames)
+}
+
+/// Finds the shader in `shaders/` by the name of the file or the directory.
+fn load_passes(options: &Options) -> Result<Vec<Pass>, String> {
+    let name = &options.shader;
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let prefix = std::fs::read_to_string(root.join(shaders::FRAGMENT_SHADER_PREFIX))
+        .map_err(|e| e.to_string())?;
+    let validator = ShaderValidator::new(&prefix);
+    let paths = find_render_graph(&root.join("shaders"), name)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("shader {name} is not found"))?;
+    let pass_names = paths
+        .iter()
+        .map(|path| path.file_name().unwrap().to_str().unwrap())
+        .collect::<Vec<_>>();
+    paths
+        .iter()
+        .enumerate()
+        .map(|(idx, path)| {
+            let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            // the path from the root like `build.rs`
+            let display_path = path.strip_prefix(root).unwrap_or(path);
+            let display = display_path.display();
+            // the hash of the selfie is random, it is found by the original name
+            let mut shader = shaders::parse_shader(
+                source,
+                "selfie.jpg",
+                display_path.to_str().unwrap(),
+                (idx, &pass_names),
+            )
+            .map_err(|(line, msg)| format!("{display}:{line}: {msg}"))?;
+            shader.source = split_comma_assignments(&shader.source);
+            let module = validator.parse(&shader).map_err(|errors| {
+                let errors = errors
+                    .iter()
+                    .map(|(line, msg)| format!("{display}:{line}: {msg}"));
+                errors.collect::<Vec<_>>().join("\n")
+            })?;
+            let textures = shader.channels.each_ref().map(|channel| {
+                let texture = Texture::load(channel, &options.textures)?.unwrap_or_else(|e| {
+                    eprintln!("warning: {display}: {e}, the channel is empty");
+                    Texture::empty(1, 1)
+                });
+                Some(texture)
+            });
+            Ok(Pass {
+                program: Program::new(module).map_err(|e| format!("{display}: {e}"))?,
+                shader,
+                textures,
+            })
+        })
+        .collect()
+}
+
+/// Rewrites `a = x, b += a;` to `{a = x; b += a;}` as naga drops all but the last operand of a comma.
+/// The lines are kept for the error messages.
+fn split_comma_assignments(source: &str) -> String {
+    let bytes = source.as_bytes();
+    let is_ident = |c: u8| c.is_ascii_alphanumeric() || c == b'_' || c == b'.';
+    let mut res = String::with_capacity(source.len());
+    let mut start = 0;
+    for (pos, c) in bytes.iter().enumerate() {
+        if !matches!(c, b';' | b'{' | b'}' | b')') || pos < start {
+            continue;
+        }
+        // an identifier and an assignment at the start of a statement
+        let statement = pos + 1;
+        let ident = statement + source[statement..].len() - source[statement..].trim_start().len();
+        let ident_end = ident + bytes[ident..].iter().take_while(|c| is_ident(**c)).count();
+        let op = ident_end + source[ident_end..].len() - source[ident_end..].trim_start().len();
+        let op_end = op
+            + bytes[op..]
+                .iter()
+                .take_while(|c| b"+-*/=".contains(c))
+                .count();
+        let assignment = &source[op..op_end];
+        if ident == ident_end
+            || !assignment.ends_with('=')
+            || assignment.len() > 2
+            || assignment == "=="
+        {
+            continue;
+        }
+        // the commas outside of the parentheses up to the end of the statement
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let end = (op_end..bytes.len()).find(|idx| {
+            match bytes[*idx] {
+                b'(' | b'[' => depth += 1,
+                b')' | b']' => depth -= 1,
+                b',' if depth == 0 => commas.push(*idx),
+                _ => {}
+            }
+            depth < 0 || matches!(bytes[*idx], b';' | b'{' | b'}')
+        });
+        let end = match end {
+            Some(end) if !commas.is_empty() && bytes[end] == b';' => end,
+            _ => continue,
+        };
+        res += &source[start..statement];
+        res += "{";
+        commas.push(end);
+        commas.iter().fold(statement, |from, comma| {
+            res += &source[from..*comma];
+            res += ";";
+            comma + 1
+        });
+        res += "}";
+        start = end + 1;
+    }
+    res + &source[start..]
+}
+
+fn find_render_graph(dir: &Path, name: &str) -> std::io::Result<Option<Vec<PathBuf>>> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let res = match shaders::render_graph_passes(&path) {
+            Some(passes) if path.file_stem().and_then(|stem| stem.to_str()) == Some(name) => {
+                Some(passes)
+            }
+            Some(_) => None,
+            None => find_render_graph(&path, name)?,
+        };
+        if res.is_some() {
+            return Ok(res);
+        }
+    }
+    Ok(None)
+}
+
+/// depth first search as `render_graph::sort_passes`: a pass is rendered after the buffers it reads
+fn sort_passes(passes: &[Pass]) -> Vec<usize> {
+    fn visit(passes: &[Pass], idx: usize, order: &mut Vec<usize>) {
+        if order.contains(&idx) {
+            return;
+        }
+        order.push(idx);
+        let image = passes.len() - 1;
+        passes[idx].shader.channels.iter().for_each(|channel| {
+            if let Some(ChannelInput::Buffer(buffer)) = channel.input {
+                if buffer != idx && buffer != image {
+                    visit(passes, buffer, order);
+                }
+            }
+        });
+        order.retain(|i| *i != idx);
+        order.push(idx);
+    }
+    let mut order = Vec::new();
+    (0..passes.len()).for_each(|idx| visit(passes, idx, &mut order));
+    order
+}
+
+fn channels<'a>(pass: &'a Pass, buffers: &'a [Texture]) -> Channels<'a> {
+    let mut res: Channels = [None; 4];
+    pass.shader
+        .channels
+        .iter()
+        .zip(&pass.textures)
+        .enumerate()
+        .for_each(|(idx, (channel, texture))| {
+            let texture = match (&channel.input, texture) {
+                (Some(ChannelInput::Buffer(buffer)), _) => &buffers[*buffer],
+                (_, Some(texture)) => texture,
+                _ => return,
+            };
+            let (filter, wrap, _) =
+                channel
+                    .sampler()
+                    .unwrap_or((Filter::Nearest, Wrap::Clamp, false));
+            res[idx] = Some((texture, Sampler { filter, wrap }));
+        });
+    res
+}
+
+/// The date is fixed so that the images are reproducible.
+fn frame_uniforms(options: &Options, frame: usize, time: f32) -> HashMap<&'static str, Value> {
+    let [width, height] = options.size;
+    HashMap::from([
+        (
+            "iResolution",
+            Value::float(&[width as f32, height as f32, 1.0]),
+        ),
+        ("iTime", Value::float(&[time])),
+        ("iTimeDelta", Value::float(&[1.0 / options.fps])),
+        ("iFrame", Value::Int(Vector::scalar(frame as i32))),
+        ("iFrameRate", Value::float(&[options.fps])),
+        (
+            "iMouse",
+            Value::float(&[options.mouse[0], options.mouse[1], 0.0, 0.0]),
+        ),
+        ("iDate", Value::float(&[2022.0, 0.0, 1.0, time])),
+        // Shadertoy's fixed value
+        ("iSampleRate", Value::float(&[44100.0])),
+        ("iSeed", Value::float(&[options.seed])),
+        (
+            "iChannelTime",
+            Value::Composite(vec![Value::float(&[time]); 4]),
+        ),
+    ])
+}
+
+/// Runs the pass for every pixel, the rows are split among the threads.
+fn render_pass(
+    pass: &Pass,
+    uniforms: &HashMap<&str, Value>,
+    channels: &Channels,
+    [width, height]: [usize; 2],
+) -> Result<Vec<[f32; 4]>, String> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_thread = height.div_ceil(threads);
+    let mut texels = vec![[0.0; 4]; width * height];
+    std::thread::scope(|scope| {
+        let handles = texels
+            .chunks_mut(rows_per_thread * width)
+            .enumerate()
+            .map(|(chunk, texels)| {
+                scope.spawn(move || {
+                    texels.iter_mut().enumerate().try_for_each(|(idx, texel)| {
+                        let (x, y) = (idx % width, chunk * rows_per_thread + idx / width);
+                        let position = [x as f32 + 0.5, y as f32 + 0.5];
+                        *texel = pass.program.run(position, uniforms, channels)?;
+                        Ok::<_, String>(())
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().unwrap())
+    })?;
+    Ok(texels)
+}
+
+/// The rows are flipped since the buffers start from the bottom. The alpha is ignored like the canvas.
+fn to_rgba8(buffer: &Texture) -> Vec<u8> {
+    let [width, ..] = buffer.size;
+    buffer
+        .texels
+        .chunks(width)
+        .rev()
+        .flatten()
+        .flat_map(|texel| {
+            let [r, g, b, _] = texel.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+            [r, g, b, 255]
+        })
+        .collect()
+}
+
+/// A `.png` path is a PNG, animated if there are multiple frames, and other paths are directories of frames.
+fn write_frames(options: &Options, frames: &[Vec<u8>]) -> Result<(), String> {
+    let out = &options.out;
+    let error = |e: &dyn std::fmt::Display| format!("{}: {e}", out.display());
+    if out.extension().is_some_and(|ext| ext == "png") {
+        return write_png(out, options, frames).map_err(|e| error(&e));
+    }
+    std::fs::create_dir_all(out).map_err(|e| error(&e))?;
+    frames.iter().enumerate().try_for_each(|(idx, frame)| {
+        let path = out.join(format!("{idx:04}.png"));
+        write_png(&path, options, std::slice::from_ref(frame)).map_err(|e| error(&e))
+    })
+}
+
+fn write_png(path: &Path, options: &Options, frames: &[Vec<u8>]) -> Result<(), png::EncodingError> {
+    let [width, height] = options.size;
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    if frames.len() > 1 {
+        encoder.set_animated(frames.len() as u32, 0)?;
+        encoder.set_frame_delay(1, options.fps.round() as u16)?;
+    }
+    let mut writer = encoder.write_header()?;
+    frames
+        .iter()
+        .try_for_each(|frame| writer.write_image_data(frame))?;
+    writer.finish()
+}
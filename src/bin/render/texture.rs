@@ -0,0 +1,346 @@
+use super::shaders::{ChannelInput, ChannelSource, Filter, Wrap, CUBE_FACES};
+use std::path::PathBuf;
+
+/// Texels of the rows from the bottom, like the textures of WebGL without the flip.
+#[derive(Clone, Debug)]
+pub struct Texture {
+    pub kind: TextureKind,
+    /// width, height and depth, faces are counted in the depth of cube maps
+    pub size: [usize; 3],
+    pub texels: Vec<[f32; 4]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureKind {
+    Flat,
+    Cube,
+    Volume,
+}
+
+/// the sampler settings of a channel
+#[derive(Clone, Copy, Debug)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+/// the directories searched for the textures by default, `./codepage12.png` is served from `resources`
+pub const TEXTURE_DIRS: [&str; 2] = ["resources", "dist"];
+
+/// positions of +X, -X, +Y, -Y, +Z and -Z in a horizontal cross in faces, as `channel::CUBE_CROSS_FACES`
+const CUBE_CROSS_FACES: [[usize; 2]; 6] = [[2, 1], [0, 1], [1, 0], [1, 2], [1, 1], [3, 1]];
+
+/// `VOLUME_SIGNATURE` of `channel::upload_volume_binary`
+const VOLUME_SIGNATURE: u32 = 0x004e4942;
+
+impl Texture {
+    /// an empty texture, sampled as opaque black like incomplete textures of WebGL
+    pub fn empty(width: usize, height: usize) -> Self {
+        Self {
+            kind: TextureKind::Flat,
+            size: [width, height, 1],
+            texels: vec![[0.0, 0.0, 0.0, 1.0]; width * height],
+        }
+    }
+
+    /// Loads the input of the channel, or returns `None` for the buffers which are rendered.
+    /// Channels without inputs and inputs only available in a browser are empty.
+    pub fn load(channel: &ChannelSource, dirs: &[PathBuf]) -> Option<Result<Self, String>> {
+        let vflip = channel.sampler().is_some_and(|(_, _, vflip)| vflip);
+        let input = match &channel.input {
+            Some(input) => input,
+            None => return Some(Ok(Texture::empty(1, 1))),
+        };
+        Some(match input {
+            ChannelInput::Buffer(_) => return None,
+            ChannelInput::Texture(url) => read_png(url, dirs).map(|image| image.flat(vflip)),
+            ChannelInput::CubeFaces(urls) => urls
+                .iter()
+                .map(|url| read_png(url, dirs))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|faces| Texture::cube(&faces)),
+            ChannelInput::CubeCross(url) => read_png(url, dirs).map(|image| {
+                let size = image.width / 4;
+                let faces =
+                    CUBE_CROSS_FACES.map(|[x, y]| image.crop([x * size, y * size], [size; 2]));
+                Texture::cube(&faces)
+            }),
+            ChannelInput::VolumeBinary(url) => {
+                read_file(url, dirs).and_then(|bytes| volume_binary(&bytes))
+            }
+            ChannelInput::VolumeTiles(url, [columns, rows]) => read_png(url, dirs).map(|image| {
+                let (columns, rows) = (*columns as usize, *rows as usize);
+                let size = [image.width / columns, image.height / rows];
+                let slices = (0..columns * rows)
+                    .map(|z| image.crop([z % columns * size[0], z / columns * size[1]], size))
+                    .collect::<Vec<_>>();
+                Texture {
+                    kind: TextureKind::Volume,
+                    size: [size[0], size[1], slices.len()],
+                    texels: slices
+                        .iter()
+                        .flat_map(|slice| slice.texels.clone())
+                        .collect(),
+                }
+            }),
+            ChannelInput::Keyboard => Ok(Texture::empty(256, 3)),
+            ChannelInput::Audio(_) => Ok(Texture::empty(512, 2)),
+            ChannelInput::Video(_) | ChannelInput::Webcam => Ok(Texture::empty(1, 1)),
+        })
+    }
+
+    fn cube(faces: &[Image]) -> Self {
+        let size = faces[0].width;
+        Self {
+            kind: TextureKind::Cube,
+            size: [size, size, CUBE_FACES.len()],
+            texels: faces.iter().flat_map(|face| face.texels.clone()).collect(),
+        }
+    }
+
+    /// `iChannelResolution`
+    pub fn resolution(&self) -> [f32; 3] {
+        match self.kind {
+            TextureKind::Volume => self.size.map(|v| v as f32),
+            _ => [self.size[0] as f32, self.size[1] as f32, 1.0],
+        }
+    }
+
+    /// `texelFetch`, zero out of the texture
+    pub fn fetch(&self, coord: [i32; 3]) -> [f32; 4] {
+        let [width, height, depth] = self.size.map(|v| v as i32);
+        let [x, y, z] = coord;
+        match (0..width).contains(&x) && (0..height).contains(&y) && (0..depth).contains(&z) {
+            true => self.texels[((z * height + y) * width + x) as usize],
+            false => [0.0; 4],
+        }
+    }
+
+    /// `texture` at the base level, a direction for cube maps
+    pub fn sample(&self, sampler: Sampler, coord: &[f32]) -> [f32; 4] {
+        match self.kind {
+            TextureKind::Flat => self.sample_slice(sampler, 0, [coord[0], coord[1]]),
+            TextureKind::Cube => {
+                let (face, uv) = cube_face([coord[0], coord[1], coord[2]]);
+                let sampler = Sampler {
+                    wrap: Wrap::Clamp,
+                    ..sampler
+                };
+                self.sample_slice(sampler, face, uv)
+            }
+            TextureKind::Volume => {
+                let z = coord[2] * self.size[2] as f32 - 0.5;
+                match sampler.filter {
+                    Filter::Nearest => {
+                        let z = wrap(sampler.wrap, z.round() as i32, self.size[2]);
+                        self.sample_slice(sampler, z, [coord[0], coord[1]])
+                    }
+                    _ => {
+                        let (z0, t) = (z.floor(), z - z.floor());
+                        let lower = wrap(sampler.wrap, z0 as i32, self.size[2]);
+                        let upper = wrap(sampler.wrap, z0 as i32 + 1, self.size[2]);
+                        let lower = self.sample_slice(sampler, lower, [coord[0], coord[1]]);
+                        let upper = self.sample_slice(sampler, upper, [coord[0], coord[1]]);
+                        mix(lower, upper, t)
+                    }
+                }
+            }
+        }
+    }
+
+    fn sample_slice(&self, sampler: Sampler, z: usize, uv: [f32; 2]) -> [f32; 4] {
+        let [width, height, _] = self.size;
+        let texel = |x: i32, y: i32| {
+            let (x, y) = (wrap(sampler.wrap, x, width), wrap(sampler.wrap, y, height));
+            self.texels[(z * height + y) * width + x]
+        };
+        let (x, y) = (uv[0] * width as f32 - 0.5, uv[1] * height as f32 - 0.5);
+        match sampler.filter {
+            Filter::Nearest => texel(x.round() as i32, y.round() as i32),
+            // no mipmaps without derivatives
+            Filter::Linear | Filter::Mipmap => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let bottom = mix(texel(x0, y0), texel(x0 + 1, y0), tx);
+                let top = mix(texel(x0, y0 + 1), texel(x0 + 1, y0 + 1), tx);
+                mix(bottom, top, ty)
+            }
+        }
+    }
+}
+
+fn mix(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [0, 1, 2, 3].map(|idx| a[idx] * (1.0 - t) + b[idx] * t)
+}
+
+fn wrap(wrap: Wrap, idx: i32, size: usize) -> usize {
+    let size = size as i32;
+    let idx = match wrap {
+        Wrap::Clamp => idx.clamp(0, size - 1),
+        Wrap::Repeat => idx.rem_euclid(size),
+        Wrap::Mirror => {
+            let idx = idx.rem_euclid(2 * size);
+            match idx < size {
+                true => idx,
+                false => 2 * size - 1 - idx,
+            }
+        }
+    };
+    idx as usize
+}
+
+/// the face and the coordinates on it, in the table of the OpenGL ES specification
+fn cube_face([x, y, z]: [f32; 3]) -> (usize, [f32; 2]) {
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        match x >= 0.0 {
+            true => (0, -z, -y, ax),
+            false => (1, z, -y, ax),
+        }
+    } else if ay >= az {
+        match y >= 0.0 {
+            true => (2, x, z, ay),
+            false => (3, x, -z, ay),
+        }
+    } else {
+        match z >= 0.0 {
+            true => (4, x, -y, az),
+            false => (5, -x, -y, az),
+        }
+    };
+    (face, [(sc / ma + 1.0) * 0.5, (tc / ma + 1.0) * 0.5])
+}
+
+/// RGBA texels of a decoded image from the top row
+struct Image {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 4]>,
+}
+
+impl Image {
+    /// The first row of the image is uploaded first, at the bottom of the texture, unless `vflip`.
+    fn flat(mut self, vflip: bool) -> Texture {
+        if vflip {
+            self.texels = self
+                .texels
+                .chunks(self.width)
+                .rev()
+                .flatten()
+                .copied()
+                .collect();
+        }
+        Texture {
+            kind: TextureKind::Flat,
+            size: [self.width, self.height, 1],
+            texels: self.texels,
+        }
+    }
+
+    fn crop(&self, [x, y]: [usize; 2], [width, height]: [usize; 2]) -> Image {
+        let texels = (y..y + height)
+            .flat_map(|row| &self.texels[row * self.width + x..row * self.width + x + width])
+            .copied()
+            .collect();
+        Image {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+fn find_file(url: &str, dirs: &[PathBuf]) -> Result<PathBuf, String> {
+    let name = url.trim_start_matches("./");
+    dirs.iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            let dirs = dirs.iter().map(|dir| dir.display().to_string());
+            format!(
+                "{name} is not found in {}",
+                dirs.collect::<Vec<_>>().join(" or ")
+            )
+        })
+}
+
+fn read_file(url: &str, dirs: &[PathBuf]) -> Result<Vec<u8>, String> {
+    let path = find_file(url, dirs)?;
+    std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// the first bytes of a PNG file
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Only PNG files are decoded, found by the signature so that a PNG can stand in for a JPEG,
+/// e.g. `selfie.jpg` of `tests/golden/textures`.
+fn read_png(url: &str, dirs: &[PathBuf]) -> Result<Image, String> {
+    let path = find_file(url, dirs)?;
+    let error = |e: &dyn std::fmt::Display| format!("{}: {e}", path.display());
+    let bytes = std::fs::read(&path).map_err(|e| error(&e))?;
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err(format!("{url} is not a PNG file"));
+    }
+    let mut decoder = png::Decoder::new(bytes.as_slice());
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut bytes = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut bytes).map_err(|e| error(&e))?;
+    let channels = info.color_type.samples();
+    let texels = bytes[..info.buffer_size()]
+        .chunks(channels)
+        .map(|texel| {
+            let v = |idx: usize| texel[idx] as f32 / 255.0;
+            match channels {
+                1 => [v(0), v(0), v(0), 1.0],
+                2 => [v(0), v(0), v(0), v(1)],
+                3 => [v(0), v(1), v(2), 1.0],
+                _ => [v(0), v(1), v(2), v(3)],
+            }
+        })
+        .collect();
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        texels,
+    })
+}
+
+/// Shadertoy's binary format, see `channel::upload_volume_binary`.
+fn volume_binary(bytes: &[u8]) -> Result<Texture, String> {
+    let header = |offset: usize| match bytes.get(offset..offset + 4) {
+        Some(word) => Ok(u32::from_le_bytes(word.try_into().unwrap())),
+        None => Err(String::from("too short header")),
+    };
+    if header(0)? != VOLUME_SIGNATURE {
+        return Err(String::from("invalid signature"));
+    }
+    let size = [header(4)?, header(8)?, header(12)?].map(|v| v as usize);
+    let format = header(16)?;
+    let (channels, format) = ((format & 0xff) as usize, format >> 16);
+    if !(1..=4).contains(&channels) || format != 0 {
+        return Err(format!(
+            "unsupported format: {channels} channels of {format}"
+        ));
+    }
+    let length = size.iter().product::<usize>() * channels;
+    let texels = bytes
+        .get(20..20 + length)
+        .ok_or("too short data")?
+        .chunks(channels)
+        .map(|texel| {
+            let mut res = [0.0, 0.0, 0.0, 1.0];
+            texel
+                .iter()
+                .enumerate()
+                .for_each(|(idx, v)| res[idx] = *v as f32 / 255.0);
+            res
+        })
+        .collect();
+    Ok(Texture {
+        kind: TextureKind::Volume,
+        size,
+        texels,
+    })
+}
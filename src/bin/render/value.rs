@@ -0,0 +1,824 @@
+use naga::{BinaryOperator, MathFunction, RelationalFunction, ScalarKind, UnaryOperator};
+
+/// A scalar is a vector of the length 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vector<T> {
+    pub len: usize,
+    pub data: [T; 4],
+}
+
+/// column major, `data[column][row]`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix {
+    pub columns: usize,
+    pub rows: usize,
+    pub data: [[f32; 4]; 4],
+}
+
+/// a local variable in the frame of a call or a global variable, and the indices into it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pointer {
+    pub root: Root,
+    path: [u32; 4],
+    depth: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Root {
+    Local { frame: usize, index: usize },
+    Global(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Float(Vector<f32>),
+    Int(Vector<i32>),
+    Uint(Vector<u32>),
+    Bool(Vector<bool>),
+    Matrix(Matrix),
+    /// arrays and structs
+    Composite(Vec<Value>),
+    Pointer(Pointer),
+    /// the texture and the sampler of `iChannelN`
+    Image(usize),
+    Sampler(usize),
+}
+
+pub type Result<T> = std::result::Result<T, String>;
+
+impl<T: Copy + Default> Vector<T> {
+    pub fn scalar(value: T) -> Self {
+        Self::new(&[value])
+    }
+
+    pub fn new(values: &[T]) -> Self {
+        let mut data = [T::default(); 4];
+        data[..values.len()].copy_from_slice(values);
+        Self {
+            len: values.len(),
+            data,
+        }
+    }
+
+    /// A scalar is broadcast to any length.
+    pub fn get(&self, idx: usize) -> T {
+        match self.len {
+            1 => self.data[0],
+            _ => self.data[idx.min(self.len - 1)],
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data[..self.len]
+    }
+
+    pub fn map<U: Copy + Default>(self, f: impl Fn(T) -> U) -> Vector<U> {
+        let mut data = [U::default(); 4];
+        (0..self.len).for_each(|idx| data[idx] = f(self.data[idx]));
+        Vector {
+            len: self.len,
+            data,
+        }
+    }
+
+    pub fn zip<U: Copy + Default>(self, other: Self, f: impl Fn(T, T) -> U) -> Vector<U> {
+        let len = self.len.max(other.len);
+        let mut data = [U::default(); 4];
+        (0..len).for_each(|idx| data[idx] = f(self.get(idx), other.get(idx)));
+        Vector { len, data }
+    }
+
+    pub fn zip3<U: Copy + Default>(self, b: Self, c: Self, f: impl Fn(T, T, T) -> U) -> Vector<U> {
+        let len = self.len.max(b.len).max(c.len);
+        let mut data = [U::default(); 4];
+        (0..len).for_each(|idx| data[idx] = f(self.get(idx), b.get(idx), c.get(idx)));
+        Vector { len, data }
+    }
+}
+
+impl Vector<f32> {
+    fn dot(self, other: Self) -> f32 {
+        (0..self.len)
+            .map(|idx| self.data[idx] * other.get(idx))
+            .sum()
+    }
+
+    fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+}
+
+impl Matrix {
+    fn column(&self, idx: usize) -> Vector<f32> {
+        let mut column = Vector::new(&self.data[idx.min(self.columns - 1)]);
+        column.len = self.rows;
+        column
+    }
+
+    fn map(mut self, f: impl Fn(f32) -> f32) -> Self {
+        self.data.iter_mut().flatten().for_each(|v| *v = f(*v));
+        self
+    }
+
+    fn zip(mut self, other: &Self, f: impl Fn(f32, f32) -> f32) -> Self {
+        (0..4).for_each(|c| {
+            (0..4).for_each(|r| self.data[c][r] = f(self.data[c][r], other.data[c][r]))
+        });
+        self
+    }
+
+    fn transpose(&self) -> Self {
+        let mut data = [[0.0; 4]; 4];
+        (0..self.columns).for_each(|c| (0..self.rows).for_each(|r| data[r][c] = self.data[c][r]));
+        Self {
+            columns: self.rows,
+            rows: self.columns,
+            data,
+        }
+    }
+
+    fn multiply(&self, other: &Self) -> Self {
+        let mut data = [[0.0; 4]; 4];
+        (0..other.columns).for_each(|c| {
+            (0..self.rows).for_each(|r| {
+                data[c][r] = (0..self.columns)
+                    .map(|k| self.data[k][r] * other.data[c][k])
+                    .sum();
+            })
+        });
+        Self {
+            columns: other.columns,
+            rows: self.rows,
+            data,
+        }
+    }
+
+    fn multiply_vector(&self, vector: Vector<f32>) -> Vector<f32> {
+        let mut res = Vector::new(&[0.0; 4]);
+        res.len = self.rows;
+        (0..self.rows).for_each(|r| {
+            res.data[r] = (0..self.columns)
+                .map(|c| self.data[c][r] * vector.get(c))
+                .sum()
+        });
+        res
+    }
+
+    /// cofactor expansion along the first column
+    fn determinant(&self) -> f32 {
+        if self.columns == 1 {
+            return self.data[0][0];
+        }
+        (0..self.columns)
+            .map(|r| {
+                let sign = if r % 2 == 0 { 1.0 } else { -1.0 };
+                sign * self.data[0][r] * self.minor(0, r).determinant()
+            })
+            .sum()
+    }
+
+    fn minor(&self, column: usize, row: usize) -> Self {
+        let mut data = [[0.0; 4]; 4];
+        let columns = (0..self.columns).filter(|c| *c != column);
+        columns.enumerate().for_each(|(i, c)| {
+            let rows = (0..self.rows).filter(|r| *r != row);
+            rows.enumerate()
+                .for_each(|(j, r)| data[i][j] = self.data[c][r]);
+        });
+        Self {
+            columns: self.columns - 1,
+            rows: self.rows - 1,
+            data,
+        }
+    }
+
+    /// the adjugate divided by the determinant
+    fn inverse(&self) -> Self {
+        let determinant = self.determinant();
+        let mut data = [[0.0; 4]; 4];
+        (0..self.columns).for_each(|c| {
+            (0..self.rows).for_each(|r| {
+                let sign = if (c + r) % 2 == 0 { 1.0 } else { -1.0 };
+                data[r][c] = sign * self.minor(c, r).determinant() / determinant;
+            })
+        });
+        Self { data, ..*self }
+    }
+}
+
+impl Pointer {
+    pub fn new(root: Root) -> Self {
+        Self {
+            root,
+            path: [0; 4],
+            depth: 0,
+        }
+    }
+
+    pub fn path(&self) -> &[u32] {
+        &self.path[..self.depth]
+    }
+
+    pub fn access(mut self, idx: u32) -> Result<Self> {
+        *self
+            .path
+            .get_mut(self.depth)
+            .ok_or("too deep access to a variable")? = idx;
+        self.depth += 1;
+        Ok(self)
+    }
+}
+
+impl Value {
+    pub fn float(values: &[f32]) -> Self {
+        Self::Float(Vector::new(values))
+    }
+
+    pub fn zero(kind: ScalarKind, len: usize) -> Result<Self> {
+        Ok(match kind {
+            ScalarKind::Float | ScalarKind::AbstractFloat => {
+                Self::Float(Vector::new(&[0.0; 4][..len]))
+            }
+            ScalarKind::Sint | ScalarKind::AbstractInt => Self::Int(Vector::new(&[0; 4][..len])),
+            ScalarKind::Uint => Self::Uint(Vector::new(&[0; 4][..len])),
+            ScalarKind::Bool => Self::Bool(Vector::new(&[false; 4][..len])),
+        })
+    }
+
+    /// Vectors and matrices are flattened to their components.
+    pub fn components(&self) -> Result<Vec<Value>> {
+        Ok(match self {
+            Self::Float(v) => v.as_slice().iter().map(|c| Self::float(&[*c])).collect(),
+            Self::Int(v) => v
+                .as_slice()
+                .iter()
+                .map(|c| Self::Int(Vector::scalar(*c)))
+                .collect(),
+            Self::Uint(v) => v
+                .as_slice()
+                .iter()
+                .map(|c| Self::Uint(Vector::scalar(*c)))
+                .collect(),
+            Self::Bool(v) => v
+                .as_slice()
+                .iter()
+                .map(|c| Self::Bool(Vector::scalar(*c)))
+                .collect(),
+            Self::Matrix(m) => (0..m.columns)
+                .flat_map(|c| m.data[c][..m.rows].iter().map(|v| Self::float(&[*v])))
+                .collect(),
+            _ => return Err(format!("{self:?} has no components")),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Float(v) => v.len,
+            Self::Int(v) => v.len,
+            Self::Uint(v) => v.len,
+            Self::Bool(v) => v.len,
+            _ => 1,
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        match self {
+            Self::Bool(v) if v.len == 1 => Ok(v.data[0]),
+            _ => Err(format!("{self:?} is not a bool")),
+        }
+    }
+
+    pub fn as_index(&self) -> Result<u32> {
+        match self {
+            Self::Int(v) if v.len == 1 => Ok(v.data[0].max(0) as u32),
+            Self::Uint(v) if v.len == 1 => Ok(v.data[0]),
+            _ => Err(format!("{self:?} is not an index")),
+        }
+    }
+
+    pub fn as_floats(&self) -> Result<Vector<f32>> {
+        match self {
+            Self::Float(v) => Ok(*v),
+            _ => Err(format!("{self:?} is not a float")),
+        }
+    }
+
+    pub fn as_ints(&self) -> Result<Vector<i32>> {
+        match self {
+            Self::Int(v) => Ok(*v),
+            Self::Uint(v) => Ok(v.map(|v| v as i32)),
+            _ => Err(format!("{self:?} is not an int")),
+        }
+    }
+
+    /// the element of an array or a struct, the column of a matrix or the component of a vector
+    pub fn index(&self, idx: u32) -> Result<Value> {
+        let idx = idx as usize;
+        Ok(match self {
+            Self::Composite(values) => values
+                .get(idx)
+                .cloned()
+                .ok_or_else(|| format!("index {idx} out of bounds"))?,
+            Self::Matrix(m) => Self::Float(m.column(idx)),
+            Self::Float(v) => Self::float(&[v.get(idx)]),
+            Self::Int(v) => Self::Int(Vector::scalar(v.get(idx))),
+            Self::Uint(v) => Self::Uint(Vector::scalar(v.get(idx))),
+            Self::Bool(v) => Self::Bool(Vector::scalar(v.get(idx))),
+            _ => return Err(format!("{self:?} cannot be indexed")),
+        })
+    }
+
+    /// Replaces the value at `path` in this value.
+    pub fn set(&mut self, path: &[u32], value: Value) -> Result<()> {
+        let (idx, rest) = match path.split_first() {
+            Some((idx, rest)) => (*idx as usize, rest),
+            None => {
+                *self = value;
+                return Ok(());
+            }
+        };
+        match (self, &value) {
+            (Self::Composite(values), _) => values
+                .get_mut(idx)
+                .ok_or_else(|| format!("index {idx} out of bounds"))?
+                .set(rest, value)?,
+            (Self::Matrix(m), Self::Float(v)) if rest.is_empty() => {
+                m.data[idx.min(m.columns - 1)][..v.len].copy_from_slice(v.as_slice())
+            }
+            (Self::Matrix(m), Self::Float(v)) => {
+                m.data[idx.min(m.columns - 1)][(rest[0] as usize).min(m.rows - 1)] = v.data[0]
+            }
+            (Self::Float(target), Self::Float(v)) => {
+                target.data[idx.min(target.len - 1)] = v.data[0]
+            }
+            (Self::Int(target), Self::Int(v)) => target.data[idx.min(target.len - 1)] = v.data[0],
+            (Self::Uint(target), Self::Uint(v)) => target.data[idx.min(target.len - 1)] = v.data[0],
+            (Self::Bool(target), Self::Bool(v)) => target.data[idx.min(target.len - 1)] = v.data[0],
+            (target, _) => return Err(format!("cannot store {value:?} into {target:?}")),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, path: &[u32]) -> Result<Value> {
+        path.iter()
+            .try_fold(self.clone(), |value, idx| value.index(*idx))
+    }
+
+    pub fn swizzle(&self, pattern: &[naga::SwizzleComponent]) -> Result<Value> {
+        let indices = pattern.iter().map(|c| *c as usize).collect::<Vec<_>>();
+        let pick =
+            |len: usize| -> Vec<usize> { indices.iter().map(|idx| (*idx).min(len - 1)).collect() };
+        Ok(match self {
+            Self::Float(v) => Self::Float(Vector::new(
+                &pick(v.len)
+                    .iter()
+                    .map(|idx| v.data[*idx])
+                    .collect::<Vec<_>>(),
+            )),
+            Self::Int(v) => Self::Int(Vector::new(
+                &pick(v.len)
+                    .iter()
+                    .map(|idx| v.data[*idx])
+                    .collect::<Vec<_>>(),
+            )),
+            Self::Uint(v) => Self::Uint(Vector::new(
+                &pick(v.len)
+                    .iter()
+                    .map(|idx| v.data[*idx])
+                    .collect::<Vec<_>>(),
+            )),
+            Self::Bool(v) => Self::Bool(Vector::new(
+                &pick(v.len)
+                    .iter()
+                    .map(|idx| v.data[*idx])
+                    .collect::<Vec<_>>(),
+            )),
+            _ => return Err(format!("cannot swizzle {self:?}")),
+        })
+    }
+
+    pub fn splat(&self, len: usize) -> Result<Value> {
+        Ok(match self {
+            Self::Float(v) => Self::Float(Vector::new(&[v.data[0]; 4][..len])),
+            Self::Int(v) => Self::Int(Vector::new(&[v.data[0]; 4][..len])),
+            Self::Uint(v) => Self::Uint(Vector::new(&[v.data[0]; 4][..len])),
+            Self::Bool(v) => Self::Bool(Vector::new(&[v.data[0]; 4][..len])),
+            _ => return Err(format!("cannot splat {self:?}")),
+        })
+    }
+
+    /// Concatenates scalars and vectors into a vector of `kind`.
+    pub fn compose_vector(kind: ScalarKind, components: &[Value]) -> Result<Value> {
+        let values = components
+            .iter()
+            .map(Value::components)
+            .collect::<Result<Vec<_>>>()?
+            .concat();
+        let mut res = Value::zero(kind, values.len().min(4))?;
+        values
+            .into_iter()
+            .take(4)
+            .enumerate()
+            .try_for_each(|(idx, value)| res.set(&[idx as u32], value))?;
+        Ok(res)
+    }
+
+    /// from columns, or from the components in the column major order
+    pub fn compose_matrix(columns: usize, rows: usize, components: &[Value]) -> Result<Value> {
+        let mut m = Matrix {
+            columns,
+            rows,
+            data: [[0.0; 4]; 4],
+        };
+        if components.len() == columns && components.iter().all(|c| c.len() == rows) {
+            components.iter().enumerate().try_for_each(|(c, column)| {
+                m.data[c][..rows].copy_from_slice(column.as_floats()?.as_slice());
+                Ok::<_, String>(())
+            })?;
+        } else {
+            let values = components
+                .iter()
+                .map(|c| Ok(c.as_floats()?.as_slice().to_vec()))
+                .collect::<Result<Vec<_>>>()?
+                .concat();
+            values
+                .iter()
+                .take(columns * rows)
+                .enumerate()
+                .for_each(|(idx, v)| m.data[idx / rows][idx % rows] = *v);
+        }
+        Ok(Value::Matrix(m))
+    }
+
+    pub fn unary(op: UnaryOperator, value: Value) -> Result<Value> {
+        Ok(match (op, value) {
+            (UnaryOperator::Negate, Self::Float(v)) => Self::Float(v.map(|v| -v)),
+            (UnaryOperator::Negate, Self::Int(v)) => Self::Int(v.map(i32::wrapping_neg)),
+            (UnaryOperator::Negate, Self::Matrix(m)) => Self::Matrix(m.map(|v| -v)),
+            (UnaryOperator::LogicalNot, Self::Bool(v)) => Self::Bool(v.map(|v| !v)),
+            (UnaryOperator::BitwiseNot, Self::Int(v)) => Self::Int(v.map(|v| !v)),
+            (UnaryOperator::BitwiseNot, Self::Uint(v)) => Self::Uint(v.map(|v| !v)),
+            (op, value) => return Err(format!("unsupported {op:?} of {value:?}")),
+        })
+    }
+
+    pub fn binary(op: BinaryOperator, left: Value, right: Value) -> Result<Value> {
+        use BinaryOperator as Op;
+        Ok(match (left, right) {
+            (Self::Float(a), Self::Float(b)) => match op {
+                Op::Add => Self::Float(a.zip(b, |a, b| a + b)),
+                Op::Subtract => Self::Float(a.zip(b, |a, b| a - b)),
+                Op::Multiply => Self::Float(a.zip(b, |a, b| a * b)),
+                Op::Divide => Self::Float(a.zip(b, |a, b| a / b)),
+                Op::Modulo => Self::Float(a.zip(b, |a, b| a % b)),
+                _ => Self::Bool(compare(op, a, b)?),
+            },
+            (Self::Int(a), Self::Int(b)) => match op {
+                Op::Add => Self::Int(a.zip(b, i32::wrapping_add)),
+                Op::Subtract => Self::Int(a.zip(b, i32::wrapping_sub)),
+                Op::Multiply => Self::Int(a.zip(b, i32::wrapping_mul)),
+                // undefined in GLSL
+                Op::Divide => Self::Int(a.zip(b, |a, b| a.checked_div(b).unwrap_or(0))),
+                Op::Modulo => Self::Int(a.zip(b, |a, b| a.checked_rem(b).unwrap_or(0))),
+                Op::And => Self::Int(a.zip(b, |a, b| a & b)),
+                Op::InclusiveOr => Self::Int(a.zip(b, |a, b| a | b)),
+                Op::ExclusiveOr => Self::Int(a.zip(b, |a, b| a ^ b)),
+                Op::ShiftLeft => Self::Int(a.zip(b, |a, b| a.wrapping_shl(b as u32))),
+                Op::ShiftRight => Self::Int(a.zip(b, |a, b| a.wrapping_shr(b as u32))),
+                _ => Self::Bool(compare(op, a, b)?),
+            },
+            (Self::Uint(a), Self::Uint(b)) => match op {
+                Op::Add => Self::Uint(a.zip(b, u32::wrapping_add)),
+                Op::Subtract => Self::Uint(a.zip(b, u32::wrapping_sub)),
+                Op::Multiply => Self::Uint(a.zip(b, u32::wrapping_mul)),
+                Op::Divide => Self::Uint(a.zip(b, |a, b| a.checked_div(b).unwrap_or(0))),
+                Op::Modulo => Self::Uint(a.zip(b, |a, b| a.checked_rem(b).unwrap_or(0))),
+                Op::And => Self::Uint(a.zip(b, |a, b| a & b)),
+                Op::InclusiveOr => Self::Uint(a.zip(b, |a, b| a | b)),
+                Op::ExclusiveOr => Self::Uint(a.zip(b, |a, b| a ^ b)),
+                Op::ShiftLeft => Self::Uint(a.zip(b, |a, b| a.wrapping_shl(b))),
+                Op::ShiftRight => Self::Uint(a.zip(b, |a, b| a.wrapping_shr(b))),
+                _ => Self::Bool(compare(op, a, b)?),
+            },
+            // the shift amount is unsigned
+            (Self::Int(a), Self::Uint(b)) if matches!(op, Op::ShiftLeft | Op::ShiftRight) => {
+                Self::binary(op, Self::Int(a), Self::Int(b.map(|b| b as i32)))?
+            }
+            (Self::Uint(a), Self::Int(b)) if matches!(op, Op::ShiftLeft | Op::ShiftRight) => {
+                Self::binary(op, Self::Uint(a), Self::Uint(b.map(|b| b as u32)))?
+            }
+            (Self::Bool(a), Self::Bool(b)) => Self::Bool(match op {
+                Op::Equal => a.zip(b, |a, b| a == b),
+                Op::NotEqual => a.zip(b, |a, b| a != b),
+                Op::LogicalAnd | Op::And => a.zip(b, |a, b| a && b),
+                Op::LogicalOr | Op::InclusiveOr => a.zip(b, |a, b| a || b),
+                Op::ExclusiveOr => a.zip(b, |a, b| a ^ b),
+                _ => return Err(format!("unsupported {op:?} of bools")),
+            }),
+            (Self::Matrix(a), Self::Matrix(b)) => Self::Matrix(match op {
+                Op::Add => a.zip(&b, |a, b| a + b),
+                Op::Subtract => a.zip(&b, |a, b| a - b),
+                Op::Multiply => a.multiply(&b),
+                Op::Divide => a.zip(&b, |a, b| a / b),
+                _ => return Err(format!("unsupported {op:?} of matrices")),
+            }),
+            (Self::Matrix(m), Self::Float(v)) => match (op, v.len) {
+                (Op::Multiply, 1) => Self::Matrix(m.map(|a| a * v.data[0])),
+                (Op::Divide, 1) => Self::Matrix(m.map(|a| a / v.data[0])),
+                (Op::Add, 1) => Self::Matrix(m.map(|a| a + v.data[0])),
+                (Op::Subtract, 1) => Self::Matrix(m.map(|a| a - v.data[0])),
+                (Op::Multiply, _) => Self::Float(m.multiply_vector(v)),
+                _ => return Err(format!("unsupported {op:?} of a matrix and a vector")),
+            },
+            (Self::Float(v), Self::Matrix(m)) => match (op, v.len) {
+                (Op::Multiply, 1) => Self::Matrix(m.map(|a| v.data[0] * a)),
+                (Op::Divide, 1) => Self::Matrix(m.map(|a| v.data[0] / a)),
+                (Op::Add, 1) => Self::Matrix(m.map(|a| v.data[0] + a)),
+                (Op::Subtract, 1) => Self::Matrix(m.map(|a| v.data[0] - a)),
+                (Op::Multiply, _) => Self::Float(m.transpose().multiply_vector(v)),
+                _ => return Err(format!("unsupported {op:?} of a vector and a matrix")),
+            },
+            (left, right) => return Err(format!("unsupported {op:?} of {left:?} and {right:?}")),
+        })
+    }
+
+    /// `condition` chooses each component if it is a vector.
+    pub fn select(condition: Value, accept: Value, reject: Value) -> Result<Value> {
+        let condition = match condition {
+            Self::Bool(condition) => condition,
+            _ => return Err(format!("{condition:?} is not a bool")),
+        };
+        if condition.len == 1 {
+            return Ok(if condition.data[0] { accept } else { reject });
+        }
+        let pick = |idx: usize| condition.get(idx);
+        Ok(match (accept, reject) {
+            (Self::Float(a), Self::Float(b)) => Self::Float(Vector::new(
+                &(0..a.len)
+                    .map(|i| if pick(i) { a.get(i) } else { b.get(i) })
+                    .collect::<Vec<_>>(),
+            )),
+            (Self::Int(a), Self::Int(b)) => Self::Int(Vector::new(
+                &(0..a.len)
+                    .map(|i| if pick(i) { a.get(i) } else { b.get(i) })
+                    .collect::<Vec<_>>(),
+            )),
+            (Self::Uint(a), Self::Uint(b)) => Self::Uint(Vector::new(
+                &(0..a.len)
+                    .map(|i| if pick(i) { a.get(i) } else { b.get(i) })
+                    .collect::<Vec<_>>(),
+            )),
+            (Self::Bool(a), Self::Bool(b)) => Self::Bool(Vector::new(
+                &(0..a.len)
+                    .map(|i| if pick(i) { a.get(i) } else { b.get(i) })
+                    .collect::<Vec<_>>(),
+            )),
+            (a, b) => return Err(format!("cannot select {a:?} or {b:?}")),
+        })
+    }
+
+    pub fn relational(fun: RelationalFunction, value: Value) -> Result<Value> {
+        Ok(Self::Bool(match (fun, value) {
+            (RelationalFunction::All, Self::Bool(v)) => {
+                Vector::scalar(v.as_slice().iter().all(|v| *v))
+            }
+            (RelationalFunction::Any, Self::Bool(v)) => {
+                Vector::scalar(v.as_slice().iter().any(|v| *v))
+            }
+            (RelationalFunction::IsNan, Self::Float(v)) => v.map(f32::is_nan),
+            (RelationalFunction::IsInf, Self::Float(v)) => v.map(f32::is_infinite),
+            (fun, value) => return Err(format!("unsupported {fun:?} of {value:?}")),
+        }))
+    }
+
+    /// `convert` is false for bit casts.
+    pub fn cast(self, kind: ScalarKind, convert: bool) -> Result<Value> {
+        Ok(match (self, kind, convert) {
+            (Self::Float(v), ScalarKind::Float, _) => Self::Float(v),
+            (Self::Float(v), ScalarKind::Sint, true) => Self::Int(v.map(|v| v as i32)),
+            // negative floats wrap like the GPUs rather than saturate
+            (Self::Float(v), ScalarKind::Uint, true) => Self::Uint(v.map(|v| v as i64 as u32)),
+            (Self::Float(v), ScalarKind::Bool, true) => Self::Bool(v.map(|v| v != 0.0)),
+            (Self::Float(v), ScalarKind::Sint, false) => Self::Int(v.map(|v| v.to_bits() as i32)),
+            (Self::Float(v), ScalarKind::Uint, false) => Self::Uint(v.map(f32::to_bits)),
+            (Self::Int(v), ScalarKind::Float, true) => Self::Float(v.map(|v| v as f32)),
+            (Self::Int(v), ScalarKind::Float, false) => {
+                Self::Float(v.map(|v| f32::from_bits(v as u32)))
+            }
+            (Self::Int(v), ScalarKind::Sint, _) => Self::Int(v),
+            (Self::Int(v), ScalarKind::Uint, _) => Self::Uint(v.map(|v| v as u32)),
+            (Self::Int(v), ScalarKind::Bool, _) => Self::Bool(v.map(|v| v != 0)),
+            (Self::Uint(v), ScalarKind::Float, true) => Self::Float(v.map(|v| v as f32)),
+            (Self::Uint(v), ScalarKind::Float, false) => Self::Float(v.map(f32::from_bits)),
+            (Self::Uint(v), ScalarKind::Sint, _) => Self::Int(v.map(|v| v as i32)),
+            (Self::Uint(v), ScalarKind::Uint, _) => Self::Uint(v),
+            (Self::Uint(v), ScalarKind::Bool, _) => Self::Bool(v.map(|v| v != 0)),
+            (Self::Bool(v), ScalarKind::Float, _) => Self::Float(v.map(|v| v as u8 as f32)),
+            (Self::Bool(v), ScalarKind::Sint, _) => Self::Int(v.map(|v| v as i32)),
+            (Self::Bool(v), ScalarKind::Uint, _) => Self::Uint(v.map(|v| v as u32)),
+            (Self::Bool(v), ScalarKind::Bool, _) => Self::Bool(v),
+            (Self::Matrix(m), ScalarKind::Float, _) => Self::Matrix(m),
+            (value, kind, _) => return Err(format!("cannot cast {value:?} to {kind:?}")),
+        })
+    }
+
+    pub fn math(fun: MathFunction, args: &[Value]) -> Result<Value> {
+        use MathFunction as Fun;
+        let float = |idx: usize| match args.get(idx) {
+            Some(Self::Float(v)) => Ok(*v),
+            arg => Err(format!(
+                "argument {idx} of {fun:?} must be a float: {arg:?}"
+            )),
+        };
+        let map = |f: fn(f32) -> f32| Ok::<_, String>(Self::Float(float(0)?.map(f)));
+        let zip =
+            |f: fn(f32, f32) -> f32| Ok::<_, String>(Self::Float(float(0)?.zip(float(1)?, f)));
+        let res = match (fun, &args[0]) {
+            (Fun::Abs, Self::Int(v)) => Self::Int(v.map(i32::wrapping_abs)),
+            (Fun::Abs, Self::Uint(v)) => Self::Uint(*v),
+            (Fun::Min | Fun::Max | Fun::Clamp, Self::Int(_) | Self::Uint(_)) => {
+                return integer_min_max(fun, args)
+            }
+            (Fun::Sign, Self::Int(v)) => Self::Int(v.map(i32::signum)),
+            (Fun::Dot, Self::Int(a)) => {
+                let b = args[1].as_ints()?;
+                Self::Int(Vector::scalar((0..a.len).fold(0, |sum, i| {
+                    sum.wrapping_add(a.data[i].wrapping_mul(b.get(i)))
+                })))
+            }
+            (Fun::Abs, _) => map(f32::abs)?,
+            (Fun::Min, _) => zip(f32::min)?,
+            (Fun::Max, _) => zip(f32::max)?,
+            (Fun::Clamp, _) => {
+                Self::Float(float(0)?.zip3(float(1)?, float(2)?, |x, lo, hi| x.max(lo).min(hi)))
+            }
+            (Fun::Saturate, _) => map(|x| x.clamp(0.0, 1.0))?,
+            (Fun::Cos, _) => map(f32::cos)?,
+            (Fun::Cosh, _) => map(f32::cosh)?,
+            (Fun::Sin, _) => map(f32::sin)?,
+            (Fun::Sinh, _) => map(f32::sinh)?,
+            (Fun::Tan, _) => map(f32::tan)?,
+            (Fun::Tanh, _) => map(f32::tanh)?,
+            (Fun::Acos, _) => map(f32::acos)?,
+            (Fun::Asin, _) => map(f32::asin)?,
+            (Fun::Atan, _) => map(f32::atan)?,
+            (Fun::Atan2, _) => zip(f32::atan2)?,
+            (Fun::Asinh, _) => map(f32::asinh)?,
+            (Fun::Acosh, _) => map(f32::acosh)?,
+            (Fun::Atanh, _) => map(f32::atanh)?,
+            (Fun::Radians, _) => map(f32::to_radians)?,
+            (Fun::Degrees, _) => map(f32::to_degrees)?,
+            (Fun::Ceil, _) => map(f32::ceil)?,
+            (Fun::Floor, _) => map(f32::floor)?,
+            (Fun::Round, _) => map(f32::round_ties_even)?,
+            (Fun::Fract, _) => map(|x| x - x.floor())?,
+            (Fun::Trunc, _) => map(f32::trunc)?,
+            (Fun::Ldexp, _) => {
+                let exponent = args[1].as_ints()?;
+                Self::Float(float(0)?.zip(exponent.map(|e| e as f32), |x, e| x * e.exp2()))
+            }
+            (Fun::Exp, _) => map(f32::exp)?,
+            (Fun::Exp2, _) => map(f32::exp2)?,
+            (Fun::Log, _) => map(f32::ln)?,
+            (Fun::Log2, _) => map(f32::log2)?,
+            (Fun::Pow, _) => zip(f32::powf)?,
+            (Fun::Sqrt, _) => map(f32::sqrt)?,
+            (Fun::InverseSqrt, _) => map(|x| 1.0 / x.sqrt())?,
+            (Fun::Sign, _) => map(|x| {
+                if x == 0.0 || x.is_nan() {
+                    x
+                } else {
+                    x.signum()
+                }
+            })?,
+            (Fun::Fma, _) => Self::Float(float(0)?.zip3(float(1)?, float(2)?, |a, b, c| a * b + c)),
+            (Fun::Mix, _) => {
+                Self::Float(float(0)?.zip3(float(1)?, float(2)?, |x, y, a| x * (1.0 - a) + y * a))
+            }
+            (Fun::Step, _) => zip(|edge, x| if x < edge { 0.0 } else { 1.0 })?,
+            (Fun::SmoothStep, _) => {
+                Self::Float(float(0)?.zip3(float(1)?, float(2)?, |e0, e1, x| {
+                    let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+                    t * t * (3.0 - 2.0 * t)
+                }))
+            }
+            (Fun::Dot, _) => Self::float(&[float(0)?.dot(float(1)?)]),
+            (Fun::Length, _) => Self::float(&[float(0)?.length()]),
+            (Fun::Distance, _) => Self::float(&[float(0)?.zip(float(1)?, |a, b| a - b).length()]),
+            (Fun::Normalize, _) => {
+                let v = float(0)?;
+                let length = v.length();
+                Self::Float(v.map(|x| x / length))
+            }
+            (Fun::Cross, _) => {
+                let (a, b) = (float(0)?.data, float(1)?.data);
+                Self::float(&[
+                    a[1] * b[2] - a[2] * b[1],
+                    a[2] * b[0] - a[0] * b[2],
+                    a[0] * b[1] - a[1] * b[0],
+                ])
+            }
+            (Fun::FaceForward, _) => {
+                let (n, i, nref) = (float(0)?, float(1)?, float(2)?);
+                match nref.dot(i) < 0.0 {
+                    true => Self::Float(n),
+                    false => Self::Float(n.map(|x| -x)),
+                }
+            }
+            (Fun::Reflect, _) => {
+                let (i, n) = (float(0)?, float(1)?);
+                let d = n.dot(i);
+                Self::Float(i.zip(n, |i, n| i - 2.0 * d * n))
+            }
+            (Fun::Refract, _) => {
+                let (i, n, eta) = (float(0)?, float(1)?, float(2)?.data[0]);
+                let d = n.dot(i);
+                let k = 1.0 - eta * eta * (1.0 - d * d);
+                match k < 0.0 {
+                    true => Self::Float(i.map(|_| 0.0)),
+                    false => Self::Float(i.zip(n, |i, n| eta * i - (eta * d + k.sqrt()) * n)),
+                }
+            }
+            (Fun::Outer, _) => {
+                let (c, r) = (float(0)?, float(1)?);
+                let mut m = Matrix {
+                    columns: r.len,
+                    rows: c.len,
+                    data: [[0.0; 4]; 4],
+                };
+                (0..r.len)
+                    .for_each(|j| (0..c.len).for_each(|i| m.data[j][i] = c.data[i] * r.data[j]));
+                Self::Matrix(m)
+            }
+            (Fun::Transpose, Self::Matrix(m)) => Self::Matrix(m.transpose()),
+            (Fun::Determinant, Self::Matrix(m)) => Self::float(&[m.determinant()]),
+            (Fun::Inverse, Self::Matrix(m)) => Self::Matrix(m.inverse()),
+            (Fun::CountOneBits, Self::Int(v)) => Self::Int(v.map(|v| v.count_ones() as i32)),
+            (Fun::CountOneBits, Self::Uint(v)) => Self::Uint(v.map(u32::count_ones)),
+            (Fun::ReverseBits, Self::Int(v)) => Self::Int(v.map(i32::reverse_bits)),
+            (Fun::ReverseBits, Self::Uint(v)) => Self::Uint(v.map(u32::reverse_bits)),
+            (Fun::FindLsb, Self::Int(v)) => Self::Int(v.map(|v| find_lsb(v as u32))),
+            (Fun::FindLsb, Self::Uint(v)) => Self::Uint(v.map(|v| find_lsb(v) as u32)),
+            (Fun::FindMsb, Self::Int(v)) => {
+                Self::Int(v.map(|v| find_msb(if v < 0 { !v } else { v } as u32)))
+            }
+            (Fun::FindMsb, Self::Uint(v)) => Self::Uint(v.map(|v| find_msb(v) as u32)),
+            (fun, arg) => return Err(format!("unsupported {fun:?} of {arg:?}")),
+        };
+        Ok(res)
+    }
+}
+
+fn compare<T: PartialOrd + Copy + Default>(
+    op: BinaryOperator,
+    a: Vector<T>,
+    b: Vector<T>,
+) -> Result<Vector<bool>> {
+    use BinaryOperator as Op;
+    Ok(match op {
+        Op::Equal => a.zip(b, |a, b| a == b),
+        Op::NotEqual => a.zip(b, |a, b| a != b),
+        Op::Less => a.zip(b, |a, b| a < b),
+        Op::LessEqual => a.zip(b, |a, b| a <= b),
+        Op::Greater => a.zip(b, |a, b| a > b),
+        Op::GreaterEqual => a.zip(b, |a, b| a >= b),
+        _ => return Err(format!("unsupported {op:?}")),
+    })
+}
+
+fn integer_min_max(fun: MathFunction, args: &[Value]) -> Result<Value> {
+    // unlike `Ord::clamp`, `lo > hi` is not a panic
+    fn clamp<T: Ord>(x: T, lo: T, hi: T) -> T {
+        std::cmp::min(std::cmp::max(x, lo), hi)
+    }
+    Ok(match (fun, args) {
+        (MathFunction::Min, [Value::Int(a), Value::Int(b)]) => Value::Int(a.zip(*b, std::cmp::min)),
+        (MathFunction::Max, [Value::Int(a), Value::Int(b)]) => Value::Int(a.zip(*b, std::cmp::max)),
+        (MathFunction::Clamp, [Value::Int(x), Value::Int(lo), Value::Int(hi)]) => {
+            Value::Int(x.zip3(*lo, *hi, clamp))
+        }
+        (MathFunction::Min, [Value::Uint(a), Value::Uint(b)]) => {
+            Value::Uint(a.zip(*b, std::cmp::min))
+        }
+        (MathFunction::Max, [Value::Uint(a), Value::Uint(b)]) => {
+            Value::Uint(a.zip(*b, std::cmp::max))
+        }
+        (MathFunction::Clamp, [Value::Uint(x), Value::Uint(lo), Value::Uint(hi)]) => {
+            Value::Uint(x.zip3(*lo, *hi, clamp))
+        }
+        _ => return Err(format!("unsupported {fun:?} of {args:?}")),
+    })
+}
+
+/// `findLSB`, -1 for zero
+fn find_lsb(v: u32) -> i32 {
+    match v {
+        0 => -1,
+        _ => v.trailing_zeros() as i32,
+    }
+}
+
+/// `findMSB`, -1 for zero
+fn find_msb(v: u32) -> i32 {
+    31 - v.leading_zeros() as i32
+}
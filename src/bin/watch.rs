@@ -0,0 +1,146 @@
+//! Pushes the shaders in `shaders/` to the backgrounds of debug builds on every change,
+//! which recompile them in place without reloading the page.
+//!
+//! ```sh
+//! cargo run --bin watch &
+//! trunk serve --ignore shaders
+//! ```
+//!
+//! Each WebSocket message is a pass preprocessed as `build.rs` does, either
+//! `source {path}\n{line numbers of the source}\n{source}` or `error {path}\n{messages}`.
+//! The channels are not reloaded, changes of `#iChannel` need a rebuild.
+#[allow(dead_code)]
+#[path = "../../build/shaders.rs"]
+mod shaders;
+
+use shaders::{ShaderValidator, FRAGMENT_SHADER_PREFIX};
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tungstenite::{Message, WebSocket};
+
+/// the address of `hot_reload::WATCHER_URL`
+const ADDRESS: &str = "127.0.0.1:8081";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+type Clients = Arc<Mutex<Vec<WebSocket<TcpStream>>>>;
+
+fn main() -> std::io::Result<()> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let shader_dir = root.join("shaders");
+    let prefix = std::fs::read_to_string(root.join(FRAGMENT_SHADER_PREFIX))?;
+    let validator = ShaderValidator::new(&prefix);
+    let listener = TcpListener::bind(ADDRESS)?;
+    eprintln!("watching {} on ws://{ADDRESS}", shader_dir.display());
+
+    // the latest message of each pass, sent to new clients since the page may be built from older files
+    let messages = Arc::new(Mutex::new(HashMap::<PathBuf, String>::new()));
+    let clients: Clients = Default::default();
+    let (cloned_messages, cloned_clients) = (Arc::clone(&messages), Arc::clone(&clients));
+    std::thread::spawn(move || {
+        listener.incoming().flatten().for_each(|stream| {
+            let mut socket = match tungstenite::accept(stream) {
+                Ok(socket) => socket,
+                Err(e) => return eprintln!("failed to accept: {e}"),
+            };
+            let messages = cloned_messages.lock().unwrap();
+            let sent = messages
+                .values()
+                .all(|message| socket.write_message(Message::text(message)).is_ok());
+            if sent {
+                cloned_clients.lock().unwrap().push(socket);
+            }
+        })
+    });
+
+    let mut modified = HashMap::<PathBuf, SystemTime>::new();
+    loop {
+        for path in fragment_paths(&shader_dir)? {
+            // editors may replace the file while it is read, it is read again on the next poll
+            let time = match std::fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(time) => time,
+                Err(_) => continue,
+            };
+            if modified.get(&path) == Some(&time) {
+                continue;
+            }
+            let message = match pass_message(&shader_dir, &validator, &path) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            modified.insert(path.clone(), time);
+            if let Some((_, errors)) = message
+                .strip_prefix("error ")
+                .and_then(|message| message.split_once('\n'))
+            {
+                eprintln!("{errors}");
+            }
+            // the clients keep the passes of their builds at the start
+            let previous = messages.lock().unwrap().insert(path, message.clone());
+            if previous.is_some() {
+                let mut clients = clients.lock().unwrap();
+                clients.retain_mut(|socket| socket.write_message(Message::text(&message)).is_ok());
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// the `.frag` files in `dir` and its subdirectories
+fn fragment_paths(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut res = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        match path.is_dir() {
+            true => res.extend(fragment_paths(&path)?),
+            false if path.extension().is_some_and(|ext| ext == "frag") => res.push(path),
+            false => {}
+        }
+    }
+    Ok(res)
+}
+
+/// Preprocesses and validates a pass as `ShaderBuilder::set_render_graph` does.
+fn pass_message(
+    shader_dir: &Path,
+    validator: &ShaderValidator,
+    path: &Path,
+) -> std::io::Result<String> {
+    // a pass of a multi-pass directory, or a single-pass shader
+    let passes = path
+        .parent()
+        .filter(|dir| *dir != shader_dir)
+        .and_then(shaders::render_graph_passes)
+        .unwrap_or_else(|| vec![path.to_path_buf()]);
+    let pass_names = passes
+        .iter()
+        .map(|path| path.file_name().unwrap().to_str().unwrap())
+        .collect::<Vec<_>>();
+    let idx = passes.iter().position(|pass| pass == path).unwrap_or(0);
+    let display_path = path.strip_prefix(shader_dir).unwrap().to_str().unwrap();
+    let display_path = display_path.replace(std::path::MAIN_SEPARATOR, "/");
+    let source = std::fs::read_to_string(path)?;
+    // the channels are not sent, so the hash of the selfie does not matter
+    let errors =
+        match shaders::parse_shader(source, "selfie.jpg", &display_path, (idx, &pass_names)) {
+            Ok(shader) => match validator.validate(&shader) {
+                Ok(()) => {
+                    let lines = shader.source_lines.iter().map(|line| line.to_string());
+                    let lines = lines.collect::<Vec<_>>().join(" ");
+                    return Ok(format!("source {display_path}\n{lines}\n{}", shader.source));
+                }
+                Err(errors) => errors,
+            },
+            Err(error) => vec![error],
+        };
+    let errors = errors
+        .iter()
+        .map(|(line, msg)| format!("{display_path}:{line}: {msg}"));
+    Ok(format!(
+        "error {display_path}\n{}",
+        errors.collect::<Vec<_>>().join("\n")
+    ))
+}
@@ -9,21 +9,46 @@ pub struct Contents {
 
 #[derive(Clone, Debug, PartialEq, Properties)]
 pub struct Props {
-    pub doc_name: Option<String>,
+    /// the route of the document, `None` for the top
+    pub doc_path: Option<String>,
+}
+
+/// A document of `texts/` with its front matter, registered by `build.rs` in the order of the paths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Document {
+    /// the route without the leading `/`, e.g. `mathematics/lecture-movie`
+    pub path: &'static str,
+    pub title: &'static str,
+    /// the shader of the background
+    pub shader: &'static str,
+    /// `lang` of the contents, e.g. `en` or `ja`
+    pub language: &'static str,
+    pub html: &'static str,
 }
 
 mod texts {
+    use super::Document;
     include!(concat!(env!("OUT_DIR"), "/texts.rs"));
 }
 
-fn get_text(hash: Option<&str>) -> &'static str {
-    if let Some(hash) = hash {
-        texts::get_texts()
-            .get(hash)
-            .copied()
-            .unwrap_or("<h1>404 not found</h1>")
-    } else {
-        include_str!("top-contents.html")
+fn get_document(path: &str) -> Option<&'static Document> {
+    let idx = texts::DOCUMENTS
+        .binary_search_by_key(&path, |document| document.path)
+        .ok()?;
+    Some(&texts::DOCUMENTS[idx])
+}
+
+/// the document of the path, or of the file name if it is unique, e.g. `lecture-movie` of the old links
+pub fn find_document(name: &str) -> Option<&'static Document> {
+    if let Some(document) = get_document(name) {
+        return Some(document);
+    }
+    let mut same_name = texts::DOCUMENTS
+        .iter()
+        .filter(|document| document.path.rsplit('/').next() == Some(name));
+    match (same_name.next(), same_name.next()) {
+        (Some(document), None) => Some(document),
+        _ => None,
     }
 }
 
@@ -67,6 +92,16 @@ impl Component for Contents {
 
     fn rendered(&mut self, ctx: &Context<Self>, _: bool) {
         let div = self.div.cast::<HtmlDivElement>().unwrap();
-        div.set_inner_html(get_text(ctx.props().doc_name.as_deref()));
+        let document = ctx.props().doc_path.as_deref().map(find_document);
+        match document {
+            None => div.set_inner_html(include_str!("top-contents.html")),
+            Some(None) => div.set_inner_html("<h1>404 not found</h1>"),
+            Some(Some(document)) => div.set_inner_html(document.html),
+        }
+        match document.flatten() {
+            Some(document) => div.set_attribute("lang", document.language),
+            None => div.remove_attribute("lang"),
+        }
+        .unwrap_throw();
     }
 }
@@ -9,11 +9,18 @@ use web_sys::*;
 use yew::prelude::*;
 
 mod app;
+mod audioswitch;
 mod background;
 mod contents;
 mod navbar;
+mod router;
 
 fn main() {
     console_error_panic_hook::set_once();
+    // the same wasm renders the background in the worker of `BackGround`
+    if background::in_worker() {
+        background::run_worker();
+        return;
+    }
     yew::start_app::<app::App>();
 }
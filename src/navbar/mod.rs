@@ -39,9 +39,9 @@ impl Component for NavBar {
                 onmouseout={ move |_| out_cursord.store(false, Ordering::SeqCst) }
             >
             <a href="./"><img src="./logo.png" class="logo"/></a>
-            <a href="./index.html?doc=profile"><div class="text-icon">{ "Profile" }</div></a>
-            <a href="./index.html?doc=mathematics"><div class="text-icon">{ "Math" }</div></a>
-            <a href="./index.html?doc=development"><div class="text-icon">{ "Dev" }</div></a>
+            <a href="./profile"><div class="text-icon">{ "Profile" }</div></a>
+            <a href="./mathematics"><div class="text-icon">{ "Math" }</div></a>
+            <a href="./development"><div class="text-icon">{ "Dev" }</div></a>
             </div>
         }
     }
@@ -0,0 +1,145 @@
+use crate::*;
+use gloo::events::{EventListener, EventListenerOptions};
+use wasm_bindgen::{JsCast, JsValue};
+
+/// A page of the app, from the path under the base like `./mathematics/lecture-movie` or from `?doc=` of the old links.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Route {
+    Top,
+    /// a document of `texts/` by its path
+    Document(String),
+    /// `?doc=none`, the background without the contents to embed it standalone
+    Background,
+}
+
+impl Route {
+    pub fn from_location() -> Route {
+        let location = gloo::utils::window().location();
+        let path = location.pathname().expect_throw("failed to get path");
+        let search = location.search().expect_throw("failed to get query");
+        Route::from_url(&path, &search).unwrap_or(Route::Top)
+    }
+
+    /// `None` if the path is a file, e.g. an image of a document, or out of the base of the app.
+    fn from_url(path: &str, search: &str) -> Option<Route> {
+        let path = match path.strip_prefix(base_path().as_str()) {
+            Some(path) if path.is_empty() || path.starts_with('/') => path,
+            _ => return None,
+        };
+        match qstring::QString::from(search).get("doc") {
+            Some("none") => return Some(Route::Background),
+            Some(doc) => return Some(Route::from_name(doc)),
+            None => {}
+        }
+        let path = path.trim_matches('/');
+        let path = path.strip_suffix("index.html").unwrap_or(path);
+        let path = path.trim_end_matches('/');
+        match path.rsplit('/').next() {
+            None | Some("") => Some(Route::Top),
+            Some(name) if name.contains('.') => None,
+            Some(_) => Some(Route::from_name(path)),
+        }
+    }
+
+    /// the route of the document by its path, or by its file name like `./lecture-movie` of the old links
+    fn from_name(name: &str) -> Route {
+        let path = contents::find_document(name).map_or(name, |document| document.path);
+        Route::Document(path.to_string())
+    }
+
+    /// the URL relative to the base
+    pub fn href(&self) -> String {
+        match self {
+            Route::Top => String::from("./"),
+            Route::Document(path) => format!("./{path}"),
+            Route::Background => String::from("./?doc=none"),
+        }
+    }
+
+    /// the path of the document shown by the route
+    pub fn doc_path(&self) -> Option<&str> {
+        match self {
+            Route::Document(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// the document shown by the route, `None` also if it is not found
+    pub fn document(&self) -> Option<&'static contents::Document> {
+        self.doc_path().and_then(contents::find_document)
+    }
+}
+
+/// Pushes the routes of the clicked links to the history instead of loading the pages,
+/// also for the links in the HTML of the documents, and follows the back and forward buttons.
+#[derive(Debug)]
+pub struct RouteListener {
+    _handlers: Vec<EventListener>,
+}
+
+impl RouteListener {
+    pub fn set(on_route: Callback<Route>) -> RouteListener {
+        let cloned_on_route = on_route.clone();
+        let on_click = move |e: &Event| {
+            let e = MouseEvent::from(JsValue::from(e.clone()));
+            // new tabs and windows are left to the browser
+            if e.default_prevented()
+                || e.button() != 0
+                || e.ctrl_key()
+                || e.meta_key()
+                || e.shift_key()
+                || e.alt_key()
+            {
+                return;
+            }
+            if let Some(route) = e.target().and_then(|target| link_route(&target)) {
+                e.prevent_default();
+                push_route(&route);
+                cloned_on_route.emit(route);
+            }
+        };
+        let on_pop_state = move |_: &Event| on_route.emit(Route::from_location());
+        let document = gloo::utils::document();
+        let options = EventListenerOptions::enable_prevent_default();
+        let _handlers = vec![
+            EventListener::new_with_options(&document, "click", options, on_click),
+            EventListener::new(&gloo::utils::window(), "popstate", on_pop_state),
+        ];
+        RouteListener { _handlers }
+    }
+}
+
+/// the route of the link around `target` within this app, except for the anchors in the page
+fn link_route(target: &EventTarget) -> Option<Route> {
+    let anchor = target
+        .dyn_ref::<Element>()?
+        .closest("a")
+        .ok()??
+        .dyn_into::<HtmlAnchorElement>()
+        .ok()?;
+    let location = gloo::utils::window().location();
+    let same_origin = Ok(anchor.origin()) == location.origin();
+    if !same_origin || !anchor.target().is_empty() || !anchor.hash().is_empty() {
+        return None;
+    }
+    match Route::from_url(&anchor.pathname(), &anchor.search())? {
+        Route::Background => None,
+        route => Some(route),
+    }
+}
+
+/// The path of `<base>` without the trailing `/`, from `public_url` of trunk,
+/// e.g. `/yotabaito` under the subpath of the project and empty at the root of the domain.
+fn base_path() -> String {
+    let base = gloo::utils::document().base_uri().ok().flatten();
+    let url = base.and_then(|base| Url::new(&base).ok());
+    let path = url.map(|url| url.pathname()).unwrap_or_default();
+    path.trim_end_matches('/').to_string()
+}
+
+fn push_route(route: &Route) {
+    gloo::utils::window()
+        .history()
+        .and_then(|history| history.push_state_with_url(&JsValue::NULL, "", Some(&route.href())))
+        .unwrap_or_else(|e| gloo::console::log!(format!("{e:?}")));
+}
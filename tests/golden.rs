@@ -0,0 +1,179 @@
+//! Golden-image tests: every shader in `shaders/` is rendered on the CPU by `src/bin/render`
+//! at a few fixed times and mouse positions and compared with the references in `tests/golden`.
+//!
+//! ```sh
+//! cargo test --release --test golden
+//! UPDATE_GOLDEN=1 cargo test --release --test golden  # rewrites the references
+//! ```
+#[allow(dead_code)]
+#[path = "../build/shaders.rs"]
+mod shaders;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// small enough for the interpreter of the debug build
+const SIZE: [usize; 2] = [48, 27];
+
+/// `iTime` and `iMouse.xy` of the references
+const CASES: [(f32, [f32; 2]); 3] = [(0.0, [0.0, 0.0]), (1.5, [12.0, 20.0]), (7.0, [36.0, 9.0])];
+
+/// CIE76 color difference above which a pixel is a mismatch, 2.3 is just noticeable
+const MISMATCH_DELTA_E: f32 = 8.0;
+
+/// the tolerance for the pixels that flip across platforms, e.g. edges found by ray marching
+const MAX_MISMATCH_RATIO: f32 = 0.01;
+
+const MAX_MEAN_DELTA_E: f32 = 1.0;
+
+#[test]
+fn golden_images() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let golden_dir = root.join("tests/golden");
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let names = shader_names(&root.join("shaders")).unwrap();
+    assert!(!names.is_empty(), "no shaders are found");
+    let failures = names
+        .iter()
+        .flat_map(|name| CASES.iter().map(move |case| (name, case)))
+        .filter_map(|(name, (time, [x, y]))| {
+            let file = format!("{name}-{time}s.png");
+            let out = out_dir.join(&file);
+            // only the textures committed for the tests, not the ones downloaded by trunk
+            let output = Command::new(env!("CARGO_BIN_EXE_render"))
+                .arg(name)
+                .args(["--size", &format!("{}x{}", SIZE[0], SIZE[1])])
+                .args(["--time", &time.to_string()])
+                .args(["--mouse", &format!("{x},{y}")])
+                .arg("--textures")
+                .arg(golden_dir.join("textures"))
+                .arg("--out")
+                .arg(&out)
+                .output()
+                .unwrap();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !output.status.success() {
+                return Some(format!("{file}: the render failed\n{stderr}"));
+            }
+            // a texture which is not found would be rendered black
+            if let Some(warning) = stderr.lines().find(|line| line.starts_with("warning:")) {
+                return Some(format!("{file}: {warning}"));
+            }
+            let reference = golden_dir.join(&file);
+            if update {
+                std::fs::copy(&out, &reference).unwrap();
+                return None;
+            }
+            if !reference.exists() {
+                return Some(format!("{file}: the reference is missing"));
+            }
+            let difference = Difference::new(&read_png(&out), &read_png(&reference));
+            match difference.passes() {
+                true => None,
+                false => Some(format!("{file}: {difference}, see {}", out.display())),
+            }
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        failures.is_empty(),
+        "\n{}\nrun `UPDATE_GOLDEN=1 cargo test --release --test golden` if the changes are intended",
+        failures.join("\n")
+    );
+}
+
+/// the names of the shaders as `render` takes them, in subdirectories too
+fn shader_names(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    paths.sort();
+    paths.iter().try_fold(Vec::new(), |mut names, path| {
+        match shaders::render_graph_passes(path) {
+            Some(_) => names.push(path.file_stem().unwrap().to_str().unwrap().to_string()),
+            None => names.extend(shader_names(path)?),
+        }
+        Ok(names)
+    })
+}
+
+/// the width, the height and the RGBA pixels
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let file = std::fs::File::open(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba, "{}", path.display());
+    buf.truncate(info.buffer_size());
+    (info.width, info.height, buf)
+}
+
+/// the perceptual difference of two images
+struct Difference {
+    mean_delta_e: f32,
+    mismatch_ratio: f32,
+}
+
+impl Difference {
+    fn new(image: &(u32, u32, Vec<u8>), reference: &(u32, u32, Vec<u8>)) -> Self {
+        if image.0 != reference.0 || image.1 != reference.1 {
+            return Self {
+                mean_delta_e: f32::INFINITY,
+                mismatch_ratio: 1.0,
+            };
+        }
+        let delta_es = image
+            .2
+            .chunks(4)
+            .zip(reference.2.chunks(4))
+            .map(|(a, b)| {
+                let (a, b) = (lab([a[0], a[1], a[2]]), lab([b[0], b[1], b[2]]));
+                (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f32>().sqrt()
+            })
+            .collect::<Vec<_>>();
+        let count = delta_es.len().max(1) as f32;
+        Self {
+            mean_delta_e: delta_es.iter().sum::<f32>() / count,
+            mismatch_ratio: delta_es.iter().filter(|d| **d > MISMATCH_DELTA_E).count() as f32
+                / count,
+        }
+    }
+
+    fn passes(&self) -> bool {
+        self.mean_delta_e <= MAX_MEAN_DELTA_E && self.mismatch_ratio <= MAX_MISMATCH_RATIO
+    }
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mean ΔE {:.2} (max {MAX_MEAN_DELTA_E}), {:.1}% of the pixels over ΔE {MISMATCH_DELTA_E} (max {:.1}%)",
+            self.mean_delta_e,
+            self.mismatch_ratio * 100.0,
+            MAX_MISMATCH_RATIO * 100.0
+        )
+    }
+}
+
+/// CIELAB of an sRGB color under D65
+fn lab(rgb: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|c| {
+        let c = c as f32 / 255.0;
+        match c <= 0.04045 {
+            true => c / 12.92,
+            false => ((c + 0.055) / 1.055).powf(2.4),
+        }
+    });
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let [fx, fy, fz] = [x, y, z].map(|t| match t > 216.0 / 24389.0 {
+        true => t.cbrt(),
+        false => (24389.0 / 27.0 * t + 16.0) / 116.0,
+    });
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}